@@ -3,6 +3,20 @@ pub use sea_orm_migration::prelude::*;
 mod m20220101_000001_create_table;
 mod m20251211_021108_create_settings_table;
 mod m20251212_012605_create_models_table;
+mod m20251216_000001_add_context_window_to_models;
+mod m20251217_000001_create_conversation_summaries_table;
+mod m20251218_000001_add_provider_to_models;
+mod m20251219_000001_add_status_to_messages;
+mod m20251220_000001_add_error_to_messages;
+mod m20251221_000001_add_max_retries_to_models;
+mod m20251222_000001_add_model_fallbacks;
+mod m20251223_000001_add_capabilities_to_models;
+mod m20251224_000001_add_generation_parameters;
+mod m20251225_000001_create_personas_table;
+mod m20251226_000001_add_parent_to_messages;
+mod m20251227_000001_add_message_tree;
+mod m20251228_000001_reset_conversation_models;
+mod m20251229_000001_add_title_is_auto;
 
 pub struct Migrator;
 
@@ -13,6 +27,20 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000001_create_table::Migration),
             Box::new(m20251211_021108_create_settings_table::Migration),
             Box::new(m20251212_012605_create_models_table::Migration),
+            Box::new(m20251216_000001_add_context_window_to_models::Migration),
+            Box::new(m20251217_000001_create_conversation_summaries_table::Migration),
+            Box::new(m20251218_000001_add_provider_to_models::Migration),
+            Box::new(m20251219_000001_add_status_to_messages::Migration),
+            Box::new(m20251220_000001_add_error_to_messages::Migration),
+            Box::new(m20251221_000001_add_max_retries_to_models::Migration),
+            Box::new(m20251222_000001_add_model_fallbacks::Migration),
+            Box::new(m20251223_000001_add_capabilities_to_models::Migration),
+            Box::new(m20251224_000001_add_generation_parameters::Migration),
+            Box::new(m20251225_000001_create_personas_table::Migration),
+            Box::new(m20251226_000001_add_parent_to_messages::Migration),
+            Box::new(m20251227_000001_add_message_tree::Migration),
+            Box::new(m20251228_000001_reset_conversation_models::Migration),
+            Box::new(m20251229_000001_add_title_is_auto::Migration),
         ]
     }
 }
@@ -0,0 +1,36 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 给 models 表加上上下文窗口大小 (单位: token，为空则使用默认值)
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Models::Table)
+                    .add_column(ColumnDef::new(Models::ContextWindow).integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Models::Table)
+                    .drop_column(Models::ContextWindow)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Models {
+    Table,
+    ContextWindow,
+}
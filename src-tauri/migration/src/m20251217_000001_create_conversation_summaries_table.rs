@@ -0,0 +1,96 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 会话摘要表：每次增量生成都插入一行，最新一行覆盖到 last_message_id 为止的所有消息
+        manager
+            .create_table(
+                Table::create()
+                    .table(ConversationSummaries::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ConversationSummaries::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ConversationSummaries::ConversationId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ConversationSummaries::LastMessageId)
+                            .integer()
+                            .not_null(),
+                    ) // 摘要覆盖到的最后一条消息
+                    .col(
+                        ColumnDef::new(ConversationSummaries::Content)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ConversationSummaries::CreatedAt)
+                            .timestamp()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-summary-conversation")
+                            .from(
+                                ConversationSummaries::Table,
+                                ConversationSummaries::ConversationId,
+                            )
+                            .to(Conversations::Table, Conversations::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-summary-message")
+                            .from(
+                                ConversationSummaries::Table,
+                                ConversationSummaries::LastMessageId,
+                            )
+                            .to(Messages::Table, Messages::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ConversationSummaries::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ConversationSummaries {
+    Table,
+    Id,
+    ConversationId,
+    LastMessageId,
+    Content,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Conversations {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    Id,
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 模型使用的接口协议 (如 "openai")，决定请求怎么构造、流怎么解析
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Models::Table)
+                    .add_column(
+                        ColumnDef::new(Models::Provider)
+                            .string()
+                            .not_null()
+                            .default("openai"),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Models::Table)
+                    .drop_column(Models::Provider)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Models {
+    Table,
+    Provider,
+}
@@ -0,0 +1,89 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 模型能力 (上下文窗口之前已经加过)，默认值按最常见的聊天模型来
+        // SQLite 一次只能加一列，所以逐列添加
+        let columns = [
+            ColumnDef::new(Models::MaxOutputTokens)
+                .integer()
+                .null()
+                .to_owned(),
+            ColumnDef::new(Models::SupportsVision)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+            ColumnDef::new(Models::SupportsTools)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+            ColumnDef::new(Models::SupportsStreaming)
+                .boolean()
+                .not_null()
+                .default(true)
+                .to_owned(),
+            ColumnDef::new(Models::SupportsSystemRole)
+                .boolean()
+                .not_null()
+                .default(true)
+                .to_owned(),
+            ColumnDef::new(Models::Reasoning)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+        ];
+
+        for column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Models::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let columns = [
+            Models::Reasoning,
+            Models::SupportsSystemRole,
+            Models::SupportsStreaming,
+            Models::SupportsTools,
+            Models::SupportsVision,
+            Models::MaxOutputTokens,
+        ];
+
+        for column in columns {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Models::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Models {
+    Table,
+    MaxOutputTokens,
+    SupportsVision,
+    SupportsTools,
+    SupportsStreaming,
+    SupportsSystemRole,
+    Reasoning,
+}
@@ -0,0 +1,124 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// 预置的几个人设 (名称, 头像, 系统提示词)
+const BUILTIN_PERSONAS: &[(&str, &str, &str)] = &[
+    (
+        "代码审查员",
+        "🧐",
+        "你是一名经验丰富的代码审查员。请指出代码中的缺陷、潜在的安全问题、性能问题和可读性问题，\
+按严重程度从高到低列出，并给出具体的修改建议和示例代码。不要重复代码本身已经表达清楚的内容。",
+    ),
+    (
+        "翻译",
+        "🌐",
+        "你是一名专业翻译。用户发来中文时翻译成英文，发来其他语言时翻译成中文。\
+保持原文的语气和格式，专业术语保留原文或采用通行译法，只输出译文，不要添加解释。",
+    ),
+    (
+        "SQL 助手",
+        "🗄️",
+        "你是一名数据库专家，擅长编写和优化 SQL。根据用户描述的需求和表结构写出正确、高效的 SQL，\
+说明用到的索引和可能的性能问题。表结构不明确时先提问，不要臆造字段。",
+    ),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 人设表：系统提示词 + 默认模型 + 默认生成参数
+        manager
+            .create_table(
+                Table::create()
+                    .table(Personas::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Personas::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Personas::Name).string().not_null())
+                    .col(ColumnDef::new(Personas::SystemPrompt).text().not_null())
+                    .col(ColumnDef::new(Personas::ModelId).integer().null()) // 为空时不指定模型
+                    .col(ColumnDef::new(Personas::Parameters).text().null()) // 生成参数 (JSON)
+                    .col(ColumnDef::new(Personas::Avatar).string().null())
+                    .col(
+                        ColumnDef::new(Personas::CreatedAt)
+                            .timestamp()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-persona-model")
+                            .from(Personas::Table, Personas::ModelId)
+                            .to(Models::Table, Models::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 会话引用的人设，为空表示不使用人设
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .add_column(ColumnDef::new(Conversations::PersonaId).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut insert = Query::insert()
+            .into_table(Personas::Table)
+            .columns([Personas::Name, Personas::Avatar, Personas::SystemPrompt])
+            .to_owned();
+        for (name, avatar, prompt) in BUILTIN_PERSONAS {
+            insert.values_panic([(*name).into(), (*avatar).into(), (*prompt).into()]);
+        }
+        manager.exec_stmt(insert).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .drop_column(Conversations::PersonaId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Personas::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Personas {
+    Table,
+    Id,
+    Name,
+    SystemPrompt,
+    ModelId,
+    Parameters,
+    Avatar,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Models {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Conversations {
+    Table,
+    PersonaId,
+}
@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // AI 回复所回答的那条用户消息；重新生成的几个版本共用同一个 parent_id
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Messages::Table)
+                    .add_column(ColumnDef::new(Messages::ParentId).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // 已有的 AI 回复挂到它前面最近的一条用户消息下
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE messages SET parent_id = (
+                    SELECT MAX(m.id) FROM messages m
+                    WHERE m.conversation_id = messages.conversation_id
+                      AND m.id < messages.id
+                      AND m.role = 'user'
+                )
+                WHERE role = 'AI'",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Messages::Table)
+                    .drop_column(Messages::ParentId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    ParentId,
+}
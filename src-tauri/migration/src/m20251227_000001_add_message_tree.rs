@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 会话当前所在分支的最后一条消息，从它沿 parent_id 往上就是当前显示的对话
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .add_column(ColumnDef::new(Conversations::ActiveLeafId).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+
+        // 之前只有 AI 回复记了 parent_id，其余消息挂到它前面的一条消息下，旧对话变成一条直线
+        db.execute_unprepared(
+            "UPDATE messages SET parent_id = (
+                SELECT MAX(m.id) FROM messages m
+                WHERE m.conversation_id = messages.conversation_id
+                  AND m.id < messages.id
+            )
+            WHERE parent_id IS NULL",
+        )
+        .await?;
+
+        db.execute_unprepared(
+            "UPDATE conversations SET active_leaf_id = (
+                SELECT MAX(m.id) FROM messages m
+                WHERE m.conversation_id = conversations.id
+            )",
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .drop_column(Conversations::ActiveLeafId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Conversations {
+    Table,
+    ActiveLeafId,
+}
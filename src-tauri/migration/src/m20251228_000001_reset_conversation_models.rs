@@ -0,0 +1,26 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // conversations.model_id 建表时默认值是 1，旧会话都指向了 1 号模型，
+        // 导致永远用不到设置的默认模型。还是默认值或者指向已删除模型的，改回 NULL (跟随默认模型)
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE conversations SET model_id = NULL
+                WHERE model_id = 1
+                   OR model_id NOT IN (SELECT id FROM models)",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // 清掉的是无效的默认值，无法也不需要恢复
+        Ok(())
+    }
+}
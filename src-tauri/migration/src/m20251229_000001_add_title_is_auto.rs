@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 标题还是占位标题、可以被自动生成的标题替换；用户改过或者已经自动起过标题后为 false
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .add_column(
+                        ColumnDef::new(Conversations::TitleIsAuto)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 之前前端新建会话时填的都是 "新对话 <时间>"，这些会话还可以自动起标题
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE conversations SET title_is_auto = 1 WHERE title LIKE '新对话%'",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .drop_column(Conversations::TitleIsAuto)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Conversations {
+    Table,
+    TitleIsAuto,
+}
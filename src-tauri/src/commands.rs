@@ -1,9 +1,18 @@
 use std::collections::HashMap;
 
 use crate::{
-    entities::{conversations, messages},
-    error::AppResult,
+    entities::{conversations, messages, models, personas},
+    error::{AppError, AppResult},
+    providers::{ollama::DEFAULT_OLLAMA_URL, GenerationParams},
+    services::{
+        ai::{SessionParameters, AUTO_TITLE_SETTING, TITLE_MODEL_SETTING},
+        chat::{HistoryMessage, STATUS_ERROR},
+        model::{CatalogModel, ModelInput},
+        ollama::{OllamaModelInfo, OllamaStatus},
+        persona::PersonaInput,
+    },
     state::AppState,
+    tokenizer::ContextBudget,
 };
 use tauri::{AppHandle, State};
 
@@ -22,33 +31,175 @@ pub async fn send_user_message(
         .save_message(session_id, "user", &content)
         .await?;
 
-    //AI 服务的调用
+    spawn_generation(app, &state, session_id, saved_msg.id);
+
+    Ok(saved_msg)
+}
+
+// AI 服务的调用 (登记到 generations 里，便于中途停止)；回复通过事件流式下发
+// reply_to 为要回答的那条用户消息
+fn spawn_generation(app: AppHandle, state: &AppState, session_id: i64, reply_to: i64) {
     let ai_service = state.services.ai.clone();
-    let prompt = content.clone();
+    state
+        .generations
+        .spawn(session_id, move |ticket| async move {
+            // 失败的详情已经通过 "ai-error" 发给前端并保存到历史里，这里只留日志
+            if let Err(e) = ai_service
+                .chat_stream(app, session_id, reply_to, ticket)
+                .await
+            {
+                eprintln!("AI 生成失败: {}", e);
+            }
+        });
+}
 
-    tauri::async_runtime::spawn(async move {
-        if let Err(e) = ai_service.chat_stream(app, session_id, prompt).await {
-            eprintln!("AI 生成失败: {}", e);
-        }
-    });
+// 重试一条生成失败的回复：删掉失败的那条，用同样的上下文重新生成
+#[tauri::command]
+pub async fn retry_generation(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    session_id: i64,
+    message_id: i64,
+) -> AppResult<()> {
+    let chat = &state.services.chat;
+    let failed = chat
+        .get_message(message_id)
+        .await?
+        .filter(|msg| msg.conversation_id == session_id && msg.status == STATUS_ERROR)
+        .ok_or_else(|| AppError::ValidationError("只能重试生成失败的回复".to_string()))?;
 
-    Ok(saved_msg)
+    let reply_to = reply_target(&failed)?;
+    chat.delete_message(failed.id).await?;
+    spawn_generation(app, &state, session_id, reply_to);
+    Ok(())
 }
 
-// Command 2: 获取历史记录
+// 重新生成一条 AI 回复：用它回答的那条用户消息之前的上下文再生成一次，
+// 旧的回复保留下来，和新回复一起作为同一条用户消息的不同版本 (新回复成为当前分支)
 #[tauri::command]
-pub async fn get_chat_history(
+pub async fn regenerate_response(
+    app: AppHandle,
     state: State<'_, AppState>,
-    session_id: i32,
+    session_id: i64,
+    message_id: i64,
+) -> AppResult<()> {
+    let chat = &state.services.chat;
+    let reply = chat
+        .get_message(message_id)
+        .await?
+        .filter(|msg| msg.conversation_id == session_id && msg.role != "user")
+        .ok_or_else(|| AppError::ValidationError("只能重新生成 AI 的回复".to_string()))?;
+
+    let reply_to = reply_target(&reply)?;
+    chat.set_active_leaf(session_id, Some(reply_to)).await?;
+    spawn_generation(app, &state, session_id, reply_to);
+    Ok(())
+}
+
+// 获取一条 AI 回复的所有版本 (包括它自己)，前端用来在版本之间切换
+#[tauri::command]
+pub async fn get_response_versions(
+    state: State<'_, AppState>,
+    message_id: i64,
 ) -> AppResult<Vec<messages::Model>> {
+    let chat = &state.services.chat;
+    let message = chat
+        .get_message(message_id)
+        .await?
+        .ok_or_else(|| AppError::ValidationError(format!("消息不存在: {}", message_id)))?;
+
+    match message.parent_id {
+        Some(parent_id) => chat.get_versions(parent_id).await,
+        None => Ok(vec![message]),
+    }
+}
+
+// AI 回复回答的那条用户消息
+fn reply_target(reply: &messages::Model) -> AppResult<i64> {
+    reply
+        .parent_id
+        .ok_or_else(|| AppError::ValidationError("这条回复前面没有用户消息".to_string()))
+}
+
+// 编辑一条用户消息并重新发送：新内容开出一条新分支，原来的对话保持不动
+#[tauri::command]
+pub async fn edit_message(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    session_id: i64,
+    message_id: i64,
+    content: String,
+) -> AppResult<messages::Model> {
+    let edited = state
+        .services
+        .chat
+        .edit_message(session_id, message_id, &content)
+        .await?;
+
+    spawn_generation(app, &state, session_id, edited.id);
+
+    Ok(edited)
+}
+
+// 切换到某条消息所在的分支，返回切换后的历史
+#[tauri::command]
+pub async fn switch_branch(
+    state: State<'_, AppState>,
+    session_id: i64,
+    message_id: i64,
+) -> AppResult<Vec<HistoryMessage>> {
+    let history = state
+        .services
+        .chat
+        .switch_branch(session_id, message_id)
+        .await?;
+    Ok(history)
+}
+
+// 停止会话中正在进行的生成 (已生成的部分会被保存，并标记为 cancelled)
+#[tauri::command]
+pub async fn stop_generation(state: State<'_, AppState>, session_id: i64) -> AppResult<bool> {
+    Ok(state.generations.cancel(session_id))
+}
+
+// Command 2: 获取历史记录 (当前分支，每条消息带上兄弟消息的 id)
+#[tauri::command]
+pub async fn get_chat_history(
+    state: State<'_, AppState>,
+    session_id: i64,
+) -> AppResult<Vec<HistoryMessage>> {
     let history = state.services.chat.get_history(session_id).await?;
     Ok(history)
 }
 
-// Command 3: 清空历史
+// Command 3: 清空一个会话的历史 (会话本身保留，正在进行的生成先停掉)
 #[tauri::command]
-pub async fn clear_chat(state: State<'_, AppState>) -> AppResult<()> {
-    state.services.chat.clear_history().await?;
+pub async fn clear_chat(state: State<'_, AppState>, session_id: i64) -> AppResult<()> {
+    state.generations.cancel_and_wait(session_id).await;
+    state.services.chat.clear_history(session_id).await?;
+    Ok(())
+}
+
+// 重命名会话
+#[tauri::command]
+pub async fn rename_conversation(
+    state: State<'_, AppState>,
+    session_id: i64,
+    title: String,
+) -> AppResult<conversations::Model> {
+    let session = state
+        .services
+        .sessions
+        .set_session_title(session_id, &title)
+        .await?;
+    Ok(session)
+}
+
+// 删除会话 (连同它的消息)，正在进行的生成先停掉
+#[tauri::command]
+pub async fn delete_conversation(state: State<'_, AppState>, session_id: i64) -> AppResult<()> {
+    state.generations.cancel_and_wait(session_id).await;
+    state.services.sessions.delete_session(session_id).await?;
     Ok(())
 }
 
@@ -56,9 +207,102 @@ pub async fn clear_chat(state: State<'_, AppState>) -> AppResult<()> {
 #[tauri::command]
 pub async fn create_new_chat(
     state: State<'_, AppState>,
-    title: String,
+    title: Option<String>,
+    model_id: Option<i64>,
+    persona_id: Option<i64>,
 ) -> AppResult<conversations::Model> {
-    let session = state.services.sessions.create_session(&title).await?;
+    let session = state
+        .services
+        .sessions
+        .create_session(title.as_deref(), model_id, persona_id)
+        .await?;
+    Ok(session)
+}
+
+// 根据会话内容重新生成标题 (新标题同时通过 "session-updated" 事件下发)
+#[tauri::command]
+pub async fn generate_title(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    session_id: i64,
+) -> AppResult<conversations::Model> {
+    let session = state.services.ai.generate_title(&app, session_id).await?;
+    Ok(session)
+}
+
+// 从某条消息处分出一个新会话 (复制到这条消息为止的对话，title 为空时自动命名)
+#[tauri::command]
+pub async fn fork_conversation(
+    state: State<'_, AppState>,
+    session_id: i64,
+    message_id: i64,
+    title: Option<String>,
+) -> AppResult<conversations::Model> {
+    let session = state
+        .services
+        .sessions
+        .fork_session(session_id, message_id, title)
+        .await?;
+    Ok(session)
+}
+
+// 切换会话使用的模型 (model_id 为空时跟随默认模型)
+#[tauri::command]
+pub async fn set_conversation_model(
+    state: State<'_, AppState>,
+    session_id: i64,
+    model_id: Option<i64>,
+) -> AppResult<conversations::Model> {
+    let session = state
+        .services
+        .sessions
+        .set_session_model(session_id, model_id)
+        .await?;
+    Ok(session)
+}
+
+// 切换会话使用的人设 (persona_id 为空时不使用人设)
+#[tauri::command]
+pub async fn set_conversation_persona(
+    state: State<'_, AppState>,
+    session_id: i64,
+    persona_id: Option<i64>,
+) -> AppResult<conversations::Model> {
+    let session = state
+        .services
+        .sessions
+        .set_session_persona(session_id, persona_id)
+        .await?;
+    Ok(session)
+}
+
+// 获取会话的生成参数 (模型默认值、会话覆盖值、实际生效值)
+#[tauri::command]
+pub async fn get_session_parameters(
+    state: State<'_, AppState>,
+    session_id: i64,
+) -> AppResult<SessionParameters> {
+    let parameters = state.services.ai.session_parameters(session_id).await?;
+    Ok(parameters)
+}
+
+// 修改会话的生成参数覆盖值，下一次生成开始生效
+#[tauri::command]
+pub async fn update_session_parameters(
+    state: State<'_, AppState>,
+    session_id: i64,
+    parameters: GenerationParams,
+) -> AppResult<conversations::Model> {
+    state
+        .services
+        .ai
+        .validate_session_overrides(session_id, &parameters)
+        .await?;
+    let session = state
+        .services
+        .sessions
+        .set_session_parameters(session_id, &parameters)
+        .await?;
     Ok(session)
 }
 
@@ -93,6 +337,30 @@ pub async fn get_settings(state: State<'_, AppState>) -> AppResult<HashMap<Strin
             .get_setting("model", "gpt-3.5-turbo")
             .await,
     );
+    map.insert(
+        "auto_summary".into(),
+        state
+            .services
+            .settings
+            .get_setting("auto_summary", "false")
+            .await,
+    );
+    map.insert(
+        AUTO_TITLE_SETTING.into(),
+        state
+            .services
+            .settings
+            .get_setting(AUTO_TITLE_SETTING, "true")
+            .await,
+    );
+    map.insert(
+        TITLE_MODEL_SETTING.into(),
+        state
+            .services
+            .settings
+            .get_setting(TITLE_MODEL_SETTING, "")
+            .await,
+    );
     Ok(map)
 }
 
@@ -107,3 +375,180 @@ pub async fn save_settings(
     }
     Ok(())
 }
+
+// 获取会话的上下文占用情况 (已用 / 可用 token)
+#[tauri::command]
+pub async fn get_context_budget(
+    state: State<'_, AppState>,
+    session_id: i64,
+) -> AppResult<ContextBudget> {
+    let budget = state.services.ai.context_budget(session_id).await?;
+    Ok(budget)
+}
+
+// 模型列表
+#[tauri::command]
+pub async fn list_models(state: State<'_, AppState>) -> AppResult<Vec<models::Model>> {
+    let models = state.services.models.list_models().await?;
+    Ok(models)
+}
+
+// 新建模型
+#[tauri::command]
+pub async fn create_model(
+    state: State<'_, AppState>,
+    input: ModelInput,
+) -> AppResult<models::Model> {
+    let model = state.services.models.create_model(input).await?;
+    Ok(model)
+}
+
+// 修改模型
+#[tauri::command]
+pub async fn update_model(
+    state: State<'_, AppState>,
+    id: i64,
+    input: ModelInput,
+) -> AppResult<models::Model> {
+    let model = state.services.models.update_model(id, input).await?;
+    Ok(model)
+}
+
+// 删除模型
+#[tauri::command]
+pub async fn delete_model(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    state.services.models.delete_model(id).await?;
+    Ok(())
+}
+
+// 设置默认模型 (会话没有指定模型时使用)
+#[tauri::command]
+pub async fn set_default_model(state: State<'_, AppState>, id: i64) -> AppResult<models::Model> {
+    let model = state.services.models.set_default_model(id).await?;
+    Ok(model)
+}
+
+// 获取默认模型
+#[tauri::command]
+pub async fn get_default_model(state: State<'_, AppState>) -> AppResult<Option<models::Model>> {
+    let model = state.services.models.get_default_model().await?;
+    Ok(model)
+}
+
+// 查询 OpenAI 兼容服务上可用的模型 (传 model_row_id 时用已有模型行的地址和 Key)
+#[tauri::command]
+pub async fn list_remote_models(
+    state: State<'_, AppState>,
+    base_url: Option<String>,
+    api_key: Option<String>,
+    model_row_id: Option<i64>,
+) -> AppResult<Vec<CatalogModel>> {
+    let models = &state.services.models;
+    let source = models
+        .catalog_source(model_row_id, base_url, api_key)
+        .await?;
+    let catalog = models.fetch_catalog(&source).await?;
+    Ok(catalog)
+}
+
+// 把选中的远端模型批量导入 models 表，返回新增的模型
+#[tauri::command]
+pub async fn import_remote_models(
+    state: State<'_, AppState>,
+    base_url: Option<String>,
+    api_key: Option<String>,
+    model_row_id: Option<i64>,
+    model_ids: Vec<String>,
+) -> AppResult<Vec<models::Model>> {
+    let models = &state.services.models;
+    let source = models
+        .catalog_source(model_row_id, base_url, api_key)
+        .await?;
+    let inserted = models.import_models(&source, model_ids).await?;
+    Ok(inserted)
+}
+
+// 人设列表
+#[tauri::command]
+pub async fn list_personas(state: State<'_, AppState>) -> AppResult<Vec<personas::Model>> {
+    let personas = state.services.personas.list_personas().await?;
+    Ok(personas)
+}
+
+// 新建人设
+#[tauri::command]
+pub async fn create_persona(
+    state: State<'_, AppState>,
+    input: PersonaInput,
+) -> AppResult<personas::Model> {
+    let persona = state.services.personas.create_persona(input).await?;
+    Ok(persona)
+}
+
+// 修改人设
+#[tauri::command]
+pub async fn update_persona(
+    state: State<'_, AppState>,
+    id: i64,
+    input: PersonaInput,
+) -> AppResult<personas::Model> {
+    let persona = state.services.personas.update_persona(id, input).await?;
+    Ok(persona)
+}
+
+// 删除人设
+#[tauri::command]
+pub async fn delete_persona(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    state.services.personas.delete_persona(id).await?;
+    Ok(())
+}
+
+// 列出本机 Ollama 已安装的模型
+#[tauri::command]
+pub async fn list_ollama_models(
+    state: State<'_, AppState>,
+    base_url: Option<String>,
+) -> AppResult<Vec<OllamaModelInfo>> {
+    let base_url = base_url.unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string());
+    let models = state.services.ollama.list_local_models(&base_url).await?;
+    Ok(models)
+}
+
+// 把本机 Ollama 的模型同步到 models 表，返回新增的模型
+#[tauri::command]
+pub async fn discover_ollama_models(
+    state: State<'_, AppState>,
+    base_url: Option<String>,
+) -> AppResult<Vec<models::Model>> {
+    let base_url = base_url.unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string());
+    let inserted = state.services.ollama.sync_models(&base_url).await?;
+    Ok(inserted)
+}
+
+// 查询 Ollama 是否在线以及已加载的模型
+#[tauri::command]
+pub async fn get_ollama_status(
+    state: State<'_, AppState>,
+    base_url: Option<String>,
+) -> AppResult<OllamaStatus> {
+    let base_url = base_url.unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string());
+    let status = state.services.ollama.status(&base_url).await?;
+    Ok(status)
+}
+
+// 下载模型 (进度通过 "ollama-pull-progress" 事件推送)
+#[tauri::command]
+pub async fn pull_ollama_model(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    base_url: Option<String>,
+    model: String,
+) -> AppResult<Vec<models::Model>> {
+    let base_url = base_url.unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string());
+    let inserted = state
+        .services
+        .ollama
+        .pull_model(&app, &base_url, &model)
+        .await?;
+    Ok(inserted)
+}
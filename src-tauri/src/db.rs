@@ -1,17 +1,15 @@
 use migration::{Migrator, MigratorTrait};
-use sea_orm::{Database, DatabaseConnection, DbErr};
+use sea_orm::{Database, DatabaseConnection};
 use std::fs;
 use tauri::{AppHandle, Manager, Runtime};
-pub async fn establish_connection<R: Runtime>(
-    app: &AppHandle<R>,
-) -> Result<DatabaseConnection, DbErr> {
-    let app_dir = app
-        .path()
-        .app_data_dir()
-        .expect("failed to get app data dir");
+
+use crate::error::AppResult;
+
+pub async fn establish_connection<R: Runtime>(app: &AppHandle<R>) -> AppResult<DatabaseConnection> {
+    let app_dir = app.path().app_data_dir()?;
 
     if !app_dir.exists() {
-        fs::create_dir_all(&app_dir).expect("failed to create app data dir");
+        fs::create_dir_all(&app_dir)?;
     }
 
     //拼接数据库路径
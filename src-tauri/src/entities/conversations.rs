@@ -11,14 +11,27 @@ pub struct Model {
     pub title: String,
     pub created_at: Option<DateTimeUtc>,
     pub model_id: Option<i64>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub parameters: Option<String>,
+    pub persona_id: Option<i64>,
+    pub active_leaf_id: Option<i64>,
+    pub title_is_auto: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::conversation_summaries::Entity")]
+    ConversationSummaries,
     #[sea_orm(has_many = "super::messages::Entity")]
     Messages,
 }
 
+impl Related<super::conversation_summaries::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ConversationSummaries.def()
+    }
+}
+
 impl Related<super::messages::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Messages.def()
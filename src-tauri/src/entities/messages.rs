@@ -13,10 +13,18 @@ pub struct Model {
     #[sea_orm(column_type = "Text")]
     pub content: String,
     pub created_at: Option<DateTimeUtc>,
+    pub status: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub error: Option<String>,
+    pub model_id: Option<i64>,
+    pub model_name: Option<String>,
+    pub parent_id: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::conversation_summaries::Entity")]
+    ConversationSummaries,
     #[sea_orm(
         belongs_to = "super::conversations::Entity",
         from = "Column::ConversationId",
@@ -27,6 +35,12 @@ pub enum Relation {
     Conversations,
 }
 
+impl Related<super::conversation_summaries::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ConversationSummaries.def()
+    }
+}
+
 impl Related<super::conversations::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Conversations.def()
@@ -2,7 +2,9 @@
 
 pub mod prelude;
 
+pub mod conversation_summaries;
 pub mod conversations;
 pub mod messages;
 pub mod models;
+pub mod personas;
 pub mod settings;
@@ -14,6 +14,19 @@ pub struct Model {
     pub api_key: Option<String>,
     pub icon: Option<String>,
     pub description: Option<String>,
+    pub context_window: Option<i64>,
+    pub provider: String,
+    pub max_retries: Option<i64>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub fallback_model_ids: Option<String>,
+    pub max_output_tokens: Option<i64>,
+    pub supports_vision: bool,
+    pub supports_tools: bool,
+    pub supports_streaming: bool,
+    pub supports_system_role: bool,
+    pub reasoning: bool,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub parameters: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
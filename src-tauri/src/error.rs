@@ -1,3 +1,6 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
 use serde::Serialize;
 use thiserror::Error;
 
@@ -12,9 +15,150 @@ pub enum AppError {
     #[error("Network error: {0}")]
     NetworkError(#[from] reqwest::Error),
 
+    // Tauri 本身的错误 (发送事件失败、拿不到应用目录等)
+    #[error("Tauri error: {0}")]
+    TauriError(#[from] tauri::Error),
+
     // 也可以定义业务错误
     #[error("AI Service Error: {0}")]
     AiError(String),
+
+    // 模型出于安全策略拒绝回答 (如 Gemini 的 SAFETY 拦截)
+    #[error("Content blocked by provider: {0}")]
+    ContentBlocked(String),
+
+    // 模型服务返回了非 2xx 响应 (已从响应体里解析出错误类型和信息)
+    #[error("Provider error ({status}): {message}")]
+    ProviderError {
+        status: u16,
+        kind: Option<String>, // 如 OpenAI 的 error.type、Gemini 的 error.status
+        message: String,
+        retry_after: Option<u64>, // 响应头 Retry-After (秒)
+    },
+
+    // 流已经开始之后，模型服务在流里返回的错误
+    #[error("Provider stream error: {message}")]
+    StreamError {
+        kind: Option<String>,
+        message: String,
+    },
+
+    // 缺少必要的配置 (如 API Key)，值为缺少的配置项
+    #[error("Missing configuration: {0}")]
+    MissingConfig(String),
+
+    // 前端传来的参数不合法
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+}
+
+impl AppError {
+    // 解析不出错误结构时，直接用响应体 (为空就用状态码的说明) 作为错误信息
+    pub fn provider(status: StatusCode, kind: Option<String>, message: impl Into<String>) -> Self {
+        let message = message.into();
+        let message = match message.trim() {
+            "" => status
+                .canonical_reason()
+                .unwrap_or("unknown error")
+                .to_string(),
+            trimmed => trimmed.to_string(),
+        };
+        AppError::ProviderError {
+            status: status.as_u16(),
+            kind,
+            message,
+            retry_after: None,
+        }
+    }
+
+    // 带上服务端建议的重试等待时间 (只对 ProviderError 有效)
+    pub fn with_retry_after(mut self, delay: Option<Duration>) -> Self {
+        if let AppError::ProviderError { retry_after, .. } = &mut self {
+            *retry_after = delay.map(|delay| delay.as_secs());
+        }
+        self
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AppError::ProviderError { retry_after, .. } => retry_after.map(Duration::from_secs),
+            _ => None,
+        }
+    }
+
+    // 发给前端的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::DbError(_) => "db_error",
+            AppError::IoError(_) => "io_error",
+            AppError::NetworkError(_) => "network_error",
+            AppError::TauriError(_) => "tauri_error",
+            AppError::AiError(_) => "ai_error",
+            AppError::ContentBlocked(_) => "content_blocked",
+            AppError::ProviderError { status, .. } => match status {
+                401 | 403 => "auth_error",
+                429 => "rate_limited",
+                500..=599 => "provider_unavailable",
+                _ => "provider_error",
+            },
+            AppError::StreamError { .. } => "stream_error",
+            AppError::MissingConfig(_) => "missing_config",
+            AppError::ValidationError(_) => "validation_error",
+        }
+    }
+
+    // 原样重试有没有可能成功 (网络抖动、限流、服务端 5xx)
+    pub fn retryable(&self) -> bool {
+        match self {
+            AppError::NetworkError(e) => !e.is_builder() && !e.is_decode(),
+            AppError::ProviderError { status, .. } => {
+                matches!(status, 408 | 409 | 429 | 500..=599)
+            }
+            AppError::StreamError { .. } => true,
+            _ => false,
+        }
+    }
+
+    // 模型服务本身出了问题 (连不上、返回错误)，换一个模型可能就好了
+    pub fn is_provider_failure(&self) -> bool {
+        matches!(
+            self,
+            AppError::NetworkError(_)
+                | AppError::ProviderError { .. }
+                | AppError::StreamError { .. }
+        )
+    }
+
+    // 附加的结构化信息，没有时为 None
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            AppError::NetworkError(e) => e.status().map(|status| {
+                serde_json::json!({ "status": status.as_u16(), "url": e.url().map(|url| url.as_str()) })
+            }),
+            AppError::ProviderError {
+                status,
+                kind,
+                retry_after,
+                ..
+            } => Some(serde_json::json!({
+                "status": status,
+                "kind": kind,
+                "retry_after": retry_after,
+            })),
+            AppError::StreamError { kind, .. } => Some(serde_json::json!({ "kind": kind })),
+            AppError::MissingConfig(key) => Some(serde_json::json!({ "key": key })),
+            _ => None,
+        }
+    }
+}
+
+// 前端拿到的错误结构：按 code 判断错误类型，message 用于展示
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+    details: Option<serde_json::Value>,
+    retryable: bool,
 }
 
 impl Serialize for AppError {
@@ -22,7 +166,13 @@ impl Serialize for AppError {
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(self.to_string().as_ref())
+        ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            details: self.details(),
+            retryable: self.retryable(),
+        }
+        .serialize(serializer)
     }
 }
 
@@ -3,12 +3,19 @@ pub mod commands;
 pub mod db;
 pub mod entities;
 pub mod error;
+pub mod providers;
+pub mod retry;
 pub mod services;
+pub mod sse;
 pub mod state;
+pub mod tokenizer;
 
 use tauri::Manager;
 
-use crate::{services::AppServices, state::AppState};
+use crate::{
+    services::AppServices,
+    state::{AppState, GenerationRegistry},
+};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -17,40 +24,66 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             commands::send_user_message,
+            commands::stop_generation,
+            commands::retry_generation,
+            commands::regenerate_response,
+            commands::get_response_versions,
+            commands::edit_message,
+            commands::switch_branch,
             commands::get_chat_history,
             commands::clear_chat,
+            commands::rename_conversation,
+            commands::delete_conversation,
             commands::create_new_chat,
+            commands::fork_conversation,
+            commands::generate_title,
+            commands::set_conversation_model,
+            commands::set_conversation_persona,
+            commands::get_session_parameters,
+            commands::update_session_parameters,
             commands::get_sessions,
             commands::get_settings,
             commands::save_settings,
+            commands::get_context_budget,
+            commands::list_models,
+            commands::create_model,
+            commands::update_model,
+            commands::delete_model,
+            commands::set_default_model,
+            commands::get_default_model,
+            commands::list_remote_models,
+            commands::import_remote_models,
+            commands::list_personas,
+            commands::create_persona,
+            commands::update_persona,
+            commands::delete_persona,
+            commands::list_ollama_models,
+            commands::discover_ollama_models,
+            commands::get_ollama_status,
+            commands::pull_ollama_model,
         ])
         .setup(|app| {
             // --- 数据库初始化开始 ---
             let handle = app.handle().clone();
 
             // 因为数据库连接是异步的，我们需要用 tauri::async_runtime
-            tauri::async_runtime::block_on(async move {
-                match db::establish_connection(&handle).await {
-                    Ok(db) => {
-                        println!("数据库连接成功！");
-                        // --- 变化在这里 ---
-                        // 以前：写了五六行来初始化
-                        // 现在：只要一行！
-                        let services = AppServices::new(&db);
+            // 初始化失败时把错误交给 Tauri，由它终止启动，而不是直接 panic
+            let db = tauri::async_runtime::block_on(db::establish_connection(&handle))
+                .inspect_err(|e| eprintln!("数据库初始化失败: {}", e))?;
+            println!("数据库连接成功！");
 
-                        handle.manage(AppState { services });
-                    }
-                    Err(e) => {
-                        eprintln!("数据库初始化失败: {}", e);
-                        // 在生产环境中，这里应该发送一个前端事件通知用户
-                        panic!("无法初始化数据库: {}", e);
-                    }
-                }
+            let services = AppServices::new(&db);
+            handle.manage(AppState {
+                services,
+                generations: GenerationRegistry::default(),
             });
             // --- 数据库初始化结束 ---
 
             Ok(())
         })
         .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .unwrap_or_else(|e| {
+            eprintln!("error while running tauri application: {}", e);
+            std::process::exit(1);
+        });
 }
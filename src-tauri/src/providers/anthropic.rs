@@ -0,0 +1,282 @@
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+use crate::providers::{ChatMessage, ChatProvider, ChatRole, ModelConfig, StreamEvent, TokenUsage};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// Messages API 要求必须带 max_tokens，模型没有配置最大输出长度时用这个
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+// --- 1. Anthropic 请求结构 ---
+#[derive(Serialize)]
+struct AnthropicMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct AnthropicRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>, // system 提示词是单独的字段，不放在 messages 里
+    messages: Vec<AnthropicMessage>,
+    stream: bool,
+    // Anthropic 没有 penalty 和 seed 参数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<&'a [String]>,
+}
+
+// --- 2. Anthropic 响应结构 (非流式) ---
+#[derive(Deserialize, Debug)]
+struct AnthropicResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+}
+
+// --- 3. Anthropic 流式事件 (按 data 里的 type 区分) ---
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicStreamEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    ContentBlockDelta {
+        delta: ContentDelta,
+    },
+    MessageDelta {
+        delta: MessageDeltaBody,
+        usage: Option<AnthropicUsage>,
+    },
+    MessageStop,
+    Error {
+        error: AnthropicErrorBody,
+    },
+    // ping、content_block_start、content_block_stop 等
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageStart {
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ContentDelta {
+    text: Option<String>, // text_delta 才有文本，其他类型的 delta 忽略
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageDeltaBody {
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicUsage {
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+}
+
+impl From<AnthropicUsage> for TokenUsage {
+    fn from(usage: AnthropicUsage) -> Self {
+        TokenUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+        }
+    }
+}
+
+// --- 4. Anthropic 错误结构 ---
+#[derive(Deserialize, Debug)]
+struct AnthropicErrorResponse {
+    error: AnthropicErrorBody,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicErrorBody {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+// Anthropic Messages API (Claude 系列)
+pub struct AnthropicProvider;
+
+impl AnthropicProvider {
+    // system 消息合并成一个 system 字段；其余消息要求 user / assistant 交替出现，
+    // 连续的同角色消息合并成一条
+    fn split_messages(messages: &[ChatMessage]) -> (Option<String>, Vec<AnthropicMessage>) {
+        let mut system = Vec::new();
+        let mut turns: Vec<AnthropicMessage> = Vec::new();
+
+        for msg in messages {
+            if msg.role == ChatRole::System {
+                system.push(msg.content.as_str());
+                continue;
+            }
+
+            let role = msg.role.as_str();
+            match turns.last_mut() {
+                Some(last) if last.role == role => {
+                    last.content.push_str("\n\n");
+                    last.content.push_str(&msg.content);
+                }
+                _ => turns.push(AnthropicMessage {
+                    role,
+                    content: msg.content.clone(),
+                }),
+            }
+        }
+
+        let system = (!system.is_empty()).then(|| system.join("\n\n"));
+        (system, turns)
+    }
+}
+
+impl ChatProvider for AnthropicProvider {
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &ModelConfig,
+        messages: &[ChatMessage],
+        stream: bool,
+    ) -> RequestBuilder {
+        let (system, messages) = Self::split_messages(messages);
+        let request_body = AnthropicRequest {
+            model: &config.model,
+            max_tokens: config.max_output_tokens().unwrap_or(DEFAULT_MAX_TOKENS),
+            system,
+            messages,
+            stream,
+            // temperature 的范围是 0~1，超出的在 GenerationParams::validate_for 里就拒绝了
+            temperature: config.params.temperature,
+            top_p: config.params.top_p,
+            stop_sequences: config.params.stop_sequences(),
+        };
+
+        client
+            .post(&config.base_url)
+            .header("x-api-key", &config.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json")
+            .json(&request_body)
+    }
+
+    fn parse_stream_event(&self, data: &str) -> AppResult<Vec<StreamEvent>> {
+        let event = match serde_json::from_str::<AnthropicStreamEvent>(data) {
+            Ok(event) => event,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        let event = match event {
+            // 开始包里带着输入 token 数
+            AnthropicStreamEvent::MessageStart { message } => {
+                message.usage.map_or(StreamEvent::Ignore, |usage| {
+                    StreamEvent::Usage(usage.into())
+                })
+            }
+            AnthropicStreamEvent::ContentBlockDelta { delta } => match delta.text {
+                Some(text) => StreamEvent::Delta(text),
+                None => StreamEvent::Ignore,
+            },
+            // 结束前会给出停止原因和输出 token 数
+            AnthropicStreamEvent::MessageDelta { delta, usage } => StreamEvent::Finish {
+                stop_reason: delta.stop_reason,
+                usage: usage.map(TokenUsage::from),
+            },
+            AnthropicStreamEvent::MessageStop => StreamEvent::Done,
+            AnthropicStreamEvent::Error { error } => {
+                return Err(AppError::StreamError {
+                    kind: Some(error.kind),
+                    message: error.message,
+                })
+            }
+            AnthropicStreamEvent::Other => StreamEvent::Ignore,
+        };
+        Ok(vec![event])
+    }
+
+    fn parse_response(&self, body: &str) -> AppResult<String> {
+        let response: AnthropicResponse = serde_json::from_str(body)
+            .map_err(|e| AppError::AiError(format!("无法解析模型响应: {}", e)))?;
+
+        let text: String = response
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .filter_map(|block| block.text)
+            .collect();
+
+        if text.is_empty() {
+            return Err(AppError::AiError("模型返回了空内容".to_string()));
+        }
+        Ok(text)
+    }
+
+    fn parse_error(&self, status: StatusCode, body: &str) -> AppError {
+        match serde_json::from_str::<AnthropicErrorResponse>(body) {
+            Ok(error) => AppError::provider(status, Some(error.error.kind), error.error.message),
+            Err(_) => AppError::provider(status, None, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> AppResult<Vec<StreamEvent>> {
+        AnthropicProvider.parse_stream_event(data)
+    }
+
+    #[test]
+    fn parses_text_delta() {
+        let events = parse(
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"你好"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(events.as_slice(), [StreamEvent::Delta(text)] if text == "你好"));
+    }
+
+    #[test]
+    fn parses_stop_reason_and_usage_from_message_delta() {
+        let events = parse(
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn","stop_sequence":null},"usage":{"output_tokens":15}}"#,
+        )
+        .unwrap();
+        match events.as_slice() {
+            [StreamEvent::Finish { stop_reason, usage }] => {
+                assert_eq!(stop_reason.as_deref(), Some("end_turn"));
+                let usage = usage.expect("message_delta 应该带用量");
+                assert_eq!(usage.output_tokens, Some(15));
+                assert_eq!(usage.input_tokens, None);
+            }
+            other => panic!("unexpected events: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps_error_event_to_stream_error() {
+        let result =
+            parse(r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#);
+        match result {
+            Err(AppError::StreamError { kind, message }) => {
+                assert_eq!(kind.as_deref(), Some("overloaded_error"));
+                assert_eq!(message, "Overloaded");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
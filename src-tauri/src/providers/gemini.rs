@@ -0,0 +1,363 @@
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+use crate::providers::{
+    ChatMessage, ChatProvider, ChatRole, GenerationParams, ModelConfig, StreamEvent, TokenUsage,
+};
+
+// 这些结束原因表示回答被安全策略拦截，而不是正常结束
+const BLOCKED_FINISH_REASONS: &[&str] = &[
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+];
+
+// --- 1. Gemini 请求结构 ---
+#[derive(Serialize)]
+struct Part {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct Content {
+    role: &'static str, // "user" 或 "model"
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize)]
+struct SystemInstruction {
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<SystemInstruction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GenerationConfig>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+
+impl GenerationConfig {
+    // 一个参数都没设置时整个 generationConfig 都不发
+    fn from_config(config: &ModelConfig) -> Option<Self> {
+        let params = &config.params;
+        if *params == GenerationParams::default() && config.max_output_tokens().is_none() {
+            return None;
+        }
+        Some(GenerationConfig {
+            max_output_tokens: config.max_output_tokens(),
+            temperature: params.temperature,
+            top_p: params.top_p,
+            presence_penalty: params.presence_penalty,
+            frequency_penalty: params.frequency_penalty,
+            stop_sequences: params.stop_sequences().map(<[String]>::to_vec),
+            seed: params.seed,
+        })
+    }
+}
+
+// --- 2. Gemini 响应结构 (流式和非流式的每个包格式相同) ---
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    prompt_feedback: Option<PromptFeedback>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<CandidateContent>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CandidateContent {
+    #[serde(default)]
+    parts: Vec<ResponsePart>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponsePart {
+    text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    prompt_token_count: Option<u32>,
+    candidates_token_count: Option<u32>,
+}
+
+impl From<UsageMetadata> for TokenUsage {
+    fn from(usage: UsageMetadata) -> Self {
+        TokenUsage {
+            input_tokens: usage.prompt_token_count,
+            output_tokens: usage.candidates_token_count,
+        }
+    }
+}
+
+// --- 3. Gemini 错误结构 ---
+#[derive(Deserialize, Debug)]
+struct GeminiErrorResponse {
+    error: GeminiErrorBody,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiErrorBody {
+    message: String,
+    status: Option<String>,
+}
+
+// Google Gemini (generateContent / streamGenerateContent)
+// base_url 填到版本号为止，如 https://generativelanguage.googleapis.com/v1beta
+pub struct GeminiProvider;
+
+impl GeminiProvider {
+    // 把统一的消息转换成 contents / parts：assistant 对应 model，
+    // system 消息放进 systemInstruction，连续的同角色消息合并
+    fn convert_messages(messages: &[ChatMessage]) -> GeminiRequest {
+        let mut system = Vec::new();
+        let mut contents: Vec<Content> = Vec::new();
+
+        for msg in messages {
+            let role = match msg.role {
+                ChatRole::System => {
+                    system.push(Part {
+                        text: msg.content.clone(),
+                    });
+                    continue;
+                }
+                ChatRole::User => "user",
+                ChatRole::Assistant => "model",
+            };
+
+            let part = Part {
+                text: msg.content.clone(),
+            };
+            match contents.last_mut() {
+                Some(last) if last.role == role => last.parts.push(part),
+                _ => contents.push(Content {
+                    role,
+                    parts: vec![part],
+                }),
+            }
+        }
+
+        GeminiRequest {
+            contents,
+            system_instruction: (!system.is_empty()).then_some(SystemInstruction { parts: system }),
+            generation_config: None,
+        }
+    }
+
+    // 被安全策略拦截时返回 ContentBlocked 错误
+    fn check_blocked(response: &GeminiResponse) -> AppResult<()> {
+        if let Some(reason) = response
+            .prompt_feedback
+            .as_ref()
+            .and_then(|feedback| feedback.block_reason.as_ref())
+        {
+            return Err(AppError::ContentBlocked(format!("提问被拦截 ({})", reason)));
+        }
+
+        for candidate in &response.candidates {
+            if let Some(reason) = candidate.finish_reason.as_deref() {
+                if BLOCKED_FINISH_REASONS.contains(&reason) {
+                    return Err(AppError::ContentBlocked(format!("回答被拦截 ({})", reason)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn candidate_text(candidate: &Candidate) -> String {
+        candidate
+            .content
+            .iter()
+            .flat_map(|content| &content.parts)
+            .filter_map(|part| part.text.as_deref())
+            .collect()
+    }
+}
+
+impl ChatProvider for GeminiProvider {
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &ModelConfig,
+        messages: &[ChatMessage],
+        stream: bool,
+    ) -> RequestBuilder {
+        let base_url = config.base_url.trim_end_matches('/');
+        let url = if stream {
+            format!("{}/models/{}:streamGenerateContent", base_url, config.model)
+        } else {
+            format!("{}/models/{}:generateContent", base_url, config.model)
+        };
+
+        // API Key 放在 x-goog-api-key 头里，不放 query (网络错误的信息里会带上完整 URL，
+        // 会随错误保存到历史里)；流式请求还要带上 alt=sse 才会返回 SSE 格式
+        let mut request = client.post(url).header("x-goog-api-key", &config.api_key);
+        if stream {
+            request = request.query(&[("alt", "sse")]);
+        }
+
+        let mut body = Self::convert_messages(messages);
+        body.generation_config = GenerationConfig::from_config(config);
+
+        request
+            .header("Content-Type", "application/json")
+            .json(&body)
+    }
+
+    fn parse_stream_event(&self, data: &str) -> AppResult<Vec<StreamEvent>> {
+        // 流里也可能直接返回错误 (要先检查：普通包的字段都是可选的，错误包也能解析成功)
+        if let Ok(error) = serde_json::from_str::<GeminiErrorResponse>(data) {
+            return Err(AppError::StreamError {
+                kind: error.error.status,
+                message: error.error.message,
+            });
+        }
+        let response = match serde_json::from_str::<GeminiResponse>(data) {
+            Ok(response) => response,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        Self::check_blocked(&response)?;
+
+        let mut events = Vec::new();
+        let usage = response.usage_metadata.map(TokenUsage::from);
+        match response.candidates.first() {
+            Some(candidate) => {
+                let text = Self::candidate_text(candidate);
+                if !text.is_empty() {
+                    events.push(StreamEvent::Delta(text));
+                }
+                // 带 finishReason 的包就是最后一个包
+                if let Some(reason) = &candidate.finish_reason {
+                    events.push(StreamEvent::Finish {
+                        stop_reason: Some(reason.clone()),
+                        usage,
+                    });
+                    events.push(StreamEvent::Done);
+                } else if let Some(usage) = usage {
+                    events.push(StreamEvent::Usage(usage));
+                }
+            }
+            None => events.extend(usage.map(StreamEvent::Usage)),
+        }
+        Ok(events)
+    }
+
+    fn parse_response(&self, body: &str) -> AppResult<String> {
+        let response: GeminiResponse = serde_json::from_str(body)
+            .map_err(|e| AppError::AiError(format!("无法解析模型响应: {}", e)))?;
+        Self::check_blocked(&response)?;
+
+        let text = response
+            .candidates
+            .first()
+            .map(Self::candidate_text)
+            .unwrap_or_default();
+        if text.is_empty() {
+            return Err(AppError::AiError("模型返回了空内容".to_string()));
+        }
+        Ok(text)
+    }
+
+    fn parse_error(&self, status: StatusCode, body: &str) -> AppError {
+        match serde_json::from_str::<GeminiErrorResponse>(body) {
+            Ok(error) => AppError::provider(status, error.error.status, error.error.message),
+            Err(_) => AppError::provider(status, None, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> AppResult<Vec<StreamEvent>> {
+        GeminiProvider.parse_stream_event(data)
+    }
+
+    #[test]
+    fn parses_text_chunk() {
+        let events =
+            parse(r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"你好"}]}}]}"#)
+                .unwrap();
+        assert!(matches!(events.as_slice(), [StreamEvent::Delta(text)] if text == "你好"));
+    }
+
+    #[test]
+    fn maps_blocked_prompt_to_content_blocked() {
+        let result = parse(r#"{"promptFeedback":{"blockReason":"SAFETY"}}"#);
+        assert!(
+            matches!(&result, Err(AppError::ContentBlocked(message)) if message.contains("SAFETY")),
+            "unexpected result: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn maps_error_payload_to_stream_error() {
+        let result = parse(
+            r#"{"error":{"code":429,"message":"Resource exhausted","status":"RESOURCE_EXHAUSTED"}}"#,
+        );
+        assert!(
+            matches!(
+                &result,
+                Err(AppError::StreamError { kind: Some(kind), message })
+                    if kind == "RESOURCE_EXHAUSTED" && message == "Resource exhausted"
+            ),
+            "unexpected result: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn maps_safety_finish_to_content_blocked() {
+        let result = parse(
+            r#"{"candidates":[{"content":{"parts":[{"text":"部分"}]},"finishReason":"SAFETY"}]}"#,
+        );
+        assert!(
+            matches!(&result, Err(AppError::ContentBlocked(message)) if message.contains("SAFETY")),
+            "unexpected result: {:?}",
+            result
+        );
+    }
+}
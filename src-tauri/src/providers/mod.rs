@@ -0,0 +1,407 @@
+use std::borrow::Cow;
+
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+use crate::retry::RetryPolicy;
+use crate::sse::SseDecoder;
+use crate::tokenizer::ContextBudget;
+
+pub mod anthropic;
+pub mod gemini;
+pub mod ollama;
+pub mod openai;
+
+// --- 1. 与厂商无关的消息结构 ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+impl ChatRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::System => "system",
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+        }
+    }
+
+    // 数据库里存的角色 ("user" / "AI") 映射成统一的角色
+    pub fn from_stored(role: &str) -> Self {
+        match role {
+            "AI" | "assistant" => ChatRole::Assistant,
+            "system" => ChatRole::System,
+            _ => ChatRole::User,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+        }
+    }
+}
+
+// --- 2. 接口协议 (存在 models 表的 provider 列里) ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProviderKind {
+    OpenAI,
+    Anthropic,
+    Gemini,
+    Ollama,
+}
+
+impl ProviderKind {
+    pub fn parse(kind: &str) -> AppResult<Self> {
+        match kind.trim().to_lowercase().as_str() {
+            "openai" => Ok(ProviderKind::OpenAI),
+            "anthropic" => Ok(ProviderKind::Anthropic),
+            "gemini" => Ok(ProviderKind::Gemini),
+            "ollama" => Ok(ProviderKind::Ollama),
+            other => Err(AppError::ValidationError(format!(
+                "不支持的模型提供方: {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAI => "openai",
+            ProviderKind::Anthropic => "anthropic",
+            ProviderKind::Gemini => "gemini",
+            ProviderKind::Ollama => "ollama",
+        }
+    }
+
+    // 本地模型 (Ollama) 不需要 API Key
+    pub fn requires_api_key(&self) -> bool {
+        !matches!(self, ProviderKind::Ollama)
+    }
+
+    pub fn provider(&self) -> Box<dyn ChatProvider> {
+        match self {
+            ProviderKind::OpenAI => Box::new(openai::OpenAIProvider),
+            ProviderKind::Anthropic => Box::new(anthropic::AnthropicProvider),
+            ProviderKind::Gemini => Box::new(gemini::GeminiProvider),
+            ProviderKind::Ollama => Box::new(ollama::OllamaProvider),
+        }
+    }
+}
+
+// 一次生成用到的模型配置
+#[derive(Clone, Debug)]
+pub struct ModelConfig {
+    pub id: Option<i64>, // models 表里的行 id (只在设置里配置、表里没有时为空)
+    pub provider: ProviderKind,
+    pub api_key: String,
+    pub base_url: String,
+    pub model: String,
+    pub context_window: usize,
+    pub retry: RetryPolicy,
+    pub capabilities: ModelCapabilities,
+    pub params: GenerationParams, // 模型默认参数 + 会话覆盖之后的最终参数
+}
+
+impl ModelConfig {
+    // 这个模型的上下文预算 (回复预留的部分参考最大输出长度)
+    pub fn context_budget(&self) -> ContextBudget {
+        ContextBudget::with_reserved_output(
+            self.context_window,
+            self.max_output_tokens().map(|tokens| tokens as usize),
+        )
+    }
+
+    // 请求里的最大输出长度：参数里设置的不能超过模型本身的上限
+    pub fn max_output_tokens(&self) -> Option<u32> {
+        let limit = self.capabilities.max_output_tokens;
+        match (self.params.max_tokens, limit) {
+            (Some(max_tokens), Some(limit)) => Some(max_tokens.min(limit)),
+            (max_tokens, limit) => max_tokens.or(limit),
+        }
+    }
+
+    // 按模型能力调整要发送的消息：不支持 system 角色的模型，
+    // 把 system 提示词合并到第一条用户消息前面
+    pub fn adapt_messages<'a>(&self, messages: &'a [ChatMessage]) -> Cow<'a, [ChatMessage]> {
+        if self.capabilities.supports_system_role
+            || !messages.iter().any(|msg| msg.role == ChatRole::System)
+        {
+            return Cow::Borrowed(messages);
+        }
+
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|msg| msg.role == ChatRole::System)
+            .map(|msg| msg.content.as_str())
+            .collect();
+        let system = system.join("\n\n");
+
+        let mut adapted: Vec<ChatMessage> = messages
+            .iter()
+            .filter(|msg| msg.role != ChatRole::System)
+            .cloned()
+            .collect();
+        match adapted.iter_mut().find(|msg| msg.role == ChatRole::User) {
+            Some(first_user) => {
+                first_user.content = format!("{}\n\n{}", system, first_user.content);
+            }
+            None => adapted.insert(0, ChatMessage::new(ChatRole::User, system)),
+        }
+        Cow::Owned(adapted)
+    }
+}
+
+// 生成参数：模型上存默认值，会话上存覆盖值，都是 JSON；没设置的字段不发给接口
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+impl GenerationParams {
+    // 读取数据库里的 JSON，为空或者格式不对时当作没有设置
+    pub fn from_json(json: Option<&str>) -> Self {
+        json.and_then(|json| serde_json::from_str(json).ok())
+            .unwrap_or_default()
+    }
+
+    // 全部没设置时存成 NULL
+    pub fn to_json(&self) -> Option<String> {
+        if *self == Self::default() {
+            return None;
+        }
+        serde_json::to_string(self).ok()
+    }
+
+    // overrides 里设置了的字段覆盖当前值
+    pub fn merge(&self, overrides: &GenerationParams) -> GenerationParams {
+        GenerationParams {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+            seed: overrides.seed.or(self.seed),
+        }
+    }
+
+    // 按各家接口共同接受的范围校验，避免把明显错误的值发出去换来一个 400
+    pub fn validate(&self) -> AppResult<()> {
+        let check = |name: &str, value: Option<f32>, min: f32, max: f32| match value {
+            Some(value) if !(min..=max).contains(&value) => Err(AppError::ValidationError(
+                format!("{} 必须在 {} 到 {} 之间", name, min, max),
+            )),
+            _ => Ok(()),
+        };
+        check("temperature", self.temperature, 0.0, 2.0)?;
+        check("top_p", self.top_p, 0.0, 1.0)?;
+        check("presence_penalty", self.presence_penalty, -2.0, 2.0)?;
+        check("frequency_penalty", self.frequency_penalty, -2.0, 2.0)?;
+
+        if self.max_tokens == Some(0) {
+            return Err(AppError::ValidationError(
+                "max_tokens 必须大于 0".to_string(),
+            ));
+        }
+        if let Some(stop) = &self.stop {
+            if stop.len() > 4 || stop.iter().any(|s| s.is_empty()) {
+                return Err(AppError::ValidationError(
+                    "stop 最多 4 个，且不能为空字符串".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // 在通用范围之外，再按具体接口的限制校验 (Anthropic 的 temperature 只接受 0~1)
+    pub fn validate_for(&self, provider: ProviderKind) -> AppResult<()> {
+        self.validate()?;
+        if provider == ProviderKind::Anthropic && self.temperature.is_some_and(|t| t > 1.0) {
+            return Err(AppError::ValidationError(
+                "Anthropic 模型的 temperature 必须在 0 到 1 之间".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // 空的 stop 列表当作没设置
+    pub fn stop_sequences(&self) -> Option<&[String]> {
+        self.stop.as_deref().filter(|stop| !stop.is_empty())
+    }
+}
+
+// 模型能力 (来自 models 表)，用来避免发出模型不支持的请求
+// 视觉和工具调用目前只做记录，供前端展示和后续功能使用
+#[derive(Clone, Copy, Debug)]
+pub struct ModelCapabilities {
+    pub max_output_tokens: Option<u32>,
+    pub supports_vision: bool,
+    pub supports_tools: bool,
+    pub supports_streaming: bool,
+    pub supports_system_role: bool,
+    pub reasoning: bool, // o1 这类推理模型：用 max_completion_tokens，不支持 system 角色等
+}
+
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        Self {
+            max_output_tokens: None,
+            supports_vision: false,
+            supports_tools: false,
+            supports_streaming: true,
+            supports_system_role: true,
+            reasoning: false,
+        }
+    }
+}
+
+// token 用量，不同接口会分几次给出，所以字段都是可选的
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct TokenUsage {
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+}
+
+impl TokenUsage {
+    // 后到的用量覆盖先到的 (只覆盖有值的字段)
+    pub fn merge(&mut self, other: TokenUsage) {
+        if other.input_tokens.is_some() {
+            self.input_tokens = other.input_tokens;
+        }
+        if other.output_tokens.is_some() {
+            self.output_tokens = other.output_tokens;
+        }
+    }
+}
+
+// 流里解析出来的一条事件
+#[derive(Debug)]
+pub enum StreamEvent {
+    Delta(String),     // 新生成的一段文本
+    Usage(TokenUsage), // token 用量
+    // 模型给出了停止原因 (如 "stop" / "end_turn" / "max_tokens")，可能顺带用量
+    Finish {
+        stop_reason: Option<String>,
+        usage: Option<TokenUsage>,
+    },
+    Done,   // 流结束
+    Ignore, // 心跳、角色包等与内容无关的事件
+}
+
+// 流式响应的分帧方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamFormat {
+    Sse,    // text/event-stream，内容在 "data:" 行里
+    Ndjson, // 每行一个 JSON (Ollama)
+}
+
+// 把响应的字节流切成一条条交给 provider 解析的数据
+pub enum PayloadDecoder {
+    Sse(SseDecoder),
+    Ndjson(Vec<u8>), // 还没凑成一整行的字节
+}
+
+impl PayloadDecoder {
+    pub fn new(format: StreamFormat) -> Self {
+        match format {
+            StreamFormat::Sse => PayloadDecoder::Sse(SseDecoder::new()),
+            StreamFormat::Ndjson => PayloadDecoder::Ndjson(Vec::new()),
+        }
+    }
+
+    // 喂入一个网络包，返回其中已经完整的数据
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        match self {
+            PayloadDecoder::Sse(decoder) => decoder
+                .push(bytes)
+                .into_iter()
+                .map(|event| event.data)
+                .collect(),
+            PayloadDecoder::Ndjson(buffer) => {
+                buffer.extend_from_slice(bytes);
+                drain_lines(buffer)
+            }
+        }
+    }
+
+    // 流结束时取出剩下的最后一条 (末尾没有换行的情况)
+    pub fn finish(&mut self) -> Vec<String> {
+        match self {
+            PayloadDecoder::Sse(decoder) => decoder
+                .finish()
+                .map(|event| event.data)
+                .into_iter()
+                .collect(),
+            PayloadDecoder::Ndjson(buffer) => {
+                buffer.push(b'\n');
+                drain_lines(buffer)
+            }
+        }
+    }
+}
+
+// 从缓冲区里取出所有完整的行，最后半行留在缓冲区里等下一个网络包
+pub fn drain_lines(buffer: &mut Vec<u8>) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+        let line: Vec<u8> = buffer.drain(..=pos).collect();
+        let line = String::from_utf8_lossy(&line).trim().to_string();
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+// --- 3. 各家接口的差异都收在这个 trait 里，AiService 的流式循环只认它 ---
+pub trait ChatProvider: Send + Sync {
+    // 构造请求 (地址、鉴权头、请求体)
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &ModelConfig,
+        messages: &[ChatMessage],
+        stream: bool,
+    ) -> RequestBuilder;
+
+    // 流式响应的格式，绝大多数接口都是 SSE
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Sse
+    }
+
+    // 解析流里的一条数据 (SSE 的 data 部分，或 NDJSON 的一行)，一条数据里可能同时带着文本、停止原因和用量
+    fn parse_stream_event(&self, data: &str) -> AppResult<Vec<StreamEvent>>;
+
+    // 解析非流式请求的完整响应，返回回复文本
+    fn parse_response(&self, body: &str) -> AppResult<String>;
+
+    // 把非 2xx 的响应体转换成错误
+    fn parse_error(&self, status: StatusCode, body: &str) -> AppError;
+}
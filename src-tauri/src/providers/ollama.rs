@@ -0,0 +1,232 @@
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+use crate::providers::{
+    ChatMessage, ChatProvider, ModelConfig, StreamEvent, StreamFormat, TokenUsage,
+};
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+// 模型行里的 base_url 可以只填 Ollama 的地址，也可以填完整的 /api/chat 地址
+pub fn api_url(base_url: &str, path: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let base_url = base_url.strip_suffix("/api/chat").unwrap_or(base_url);
+    format!("{}{}", base_url, path)
+}
+
+// --- 1. Ollama 请求结构 ---
+#[derive(Serialize)]
+struct OllamaMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct OllamaRequest<'a> {
+    model: &'a str,
+    messages: Vec<OllamaMessage<'a>>,
+    stream: bool,
+    options: OllamaOptions<'a>,
+}
+
+// 采样参数放在 options 里
+#[derive(Serialize)]
+struct OllamaOptions<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>, // 最多生成多少个 token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+
+// --- 2. Ollama 响应结构 (流式每行一个，非流式只有一个) ---
+#[derive(Deserialize, Debug)]
+struct OllamaChatResponse {
+    message: Option<OllamaResponseMessage>,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaResponseMessage {
+    content: String,
+}
+
+// Ollama 的错误就是 {"error": "..."}
+#[derive(Deserialize, Debug)]
+struct OllamaError {
+    error: String,
+}
+
+// 本地 Ollama (/api/chat)，流式响应是 NDJSON 而不是 SSE
+pub struct OllamaProvider;
+
+impl ChatProvider for OllamaProvider {
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &ModelConfig,
+        messages: &[ChatMessage],
+        stream: bool,
+    ) -> RequestBuilder {
+        let request_body = OllamaRequest {
+            model: &config.model,
+            messages: messages
+                .iter()
+                .map(|msg| OllamaMessage {
+                    role: msg.role.as_str(),
+                    content: &msg.content,
+                })
+                .collect(),
+            stream,
+            options: OllamaOptions {
+                num_predict: config.max_output_tokens(),
+                temperature: config.params.temperature,
+                top_p: config.params.top_p,
+                presence_penalty: config.params.presence_penalty,
+                frequency_penalty: config.params.frequency_penalty,
+                stop: config.params.stop_sequences(),
+                seed: config.params.seed,
+            },
+        };
+
+        let mut request = client
+            .post(api_url(&config.base_url, "/api/chat"))
+            .header("Content-Type", "application/json");
+        // 通过反向代理访问时可能需要鉴权
+        if !config.api_key.is_empty() {
+            request = request.header("Authorization", format!("Bearer {}", config.api_key));
+        }
+        request.json(&request_body)
+    }
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Ndjson
+    }
+
+    fn parse_stream_event(&self, data: &str) -> AppResult<Vec<StreamEvent>> {
+        if let Ok(error) = serde_json::from_str::<OllamaError>(data) {
+            return Err(AppError::StreamError {
+                kind: None,
+                message: error.error,
+            });
+        }
+        let response = match serde_json::from_str::<OllamaChatResponse>(data) {
+            Ok(response) => response,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        let mut events = Vec::new();
+        if let Some(message) = response.message.filter(|m| !m.content.is_empty()) {
+            events.push(StreamEvent::Delta(message.content));
+        }
+        // 最后一行 done 为 true，带着停止原因和 token 统计
+        if response.done {
+            events.push(StreamEvent::Finish {
+                stop_reason: response.done_reason,
+                usage: Some(TokenUsage {
+                    input_tokens: response.prompt_eval_count,
+                    output_tokens: response.eval_count,
+                }),
+            });
+            events.push(StreamEvent::Done);
+        }
+        Ok(events)
+    }
+
+    fn parse_response(&self, body: &str) -> AppResult<String> {
+        let response: OllamaChatResponse = serde_json::from_str(body)
+            .map_err(|e| AppError::AiError(format!("无法解析模型响应: {}", e)))?;
+
+        response
+            .message
+            .map(|message| message.content)
+            .filter(|content| !content.is_empty())
+            .ok_or_else(|| AppError::AiError("模型返回了空内容".to_string()))
+    }
+
+    fn parse_error(&self, status: StatusCode, body: &str) -> AppError {
+        match serde_json::from_str::<OllamaError>(body) {
+            // 最常见的是 404: model "xxx" not found, try pulling it first
+            Ok(error) => AppError::provider(status, None, error.error),
+            Err(_) => AppError::provider(status, None, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> AppResult<Vec<StreamEvent>> {
+        OllamaProvider.parse_stream_event(data)
+    }
+
+    #[test]
+    fn parses_content_delta() {
+        let events = parse(
+            r#"{"model":"llama3","message":{"role":"assistant","content":"你好"},"done":false}"#,
+        )
+        .unwrap();
+        assert!(matches!(events.as_slice(), [StreamEvent::Delta(text)] if text == "你好"));
+    }
+
+    #[test]
+    fn parses_final_line_with_usage() {
+        let events = parse(
+            r#"{"model":"llama3","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":26,"eval_count":298}"#,
+        )
+        .unwrap();
+        match events.as_slice() {
+            [StreamEvent::Finish { stop_reason, usage }, StreamEvent::Done] => {
+                assert_eq!(stop_reason.as_deref(), Some("stop"));
+                let usage = usage.expect("最后一行应该带用量");
+                assert_eq!(usage.input_tokens, Some(26));
+                assert_eq!(usage.output_tokens, Some(298));
+            }
+            other => panic!("unexpected events: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps_error_line_to_stream_error() {
+        let result = parse(r#"{"error":"model \"llama3\" not found, try pulling it first"}"#);
+        match result {
+            Err(AppError::StreamError { kind, message }) => {
+                assert_eq!(kind, None);
+                assert!(message.contains("not found"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn normalises_base_url() {
+        for base_url in [
+            "http://localhost:11434",
+            "http://localhost:11434/",
+            "http://localhost:11434/api/chat",
+            "http://localhost:11434/api/chat/",
+        ] {
+            assert_eq!(
+                api_url(base_url, "/api/tags"),
+                "http://localhost:11434/api/tags",
+                "base_url: {}",
+                base_url
+            );
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+use crate::providers::{ChatMessage, ChatProvider, ModelConfig, StreamEvent, TokenUsage};
+
+// --- 1. OpenAI 请求结构 ---
+#[derive(Serialize)]
+struct OpenAIMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct OpenAIRequest<'a> {
+    model: &'a str,
+    messages: Vec<OpenAIMessage<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    // 推理模型 (o1 等) 不认 max_tokens，要用 max_completion_tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool, // 让流的最后一个包带上 token 用量
+}
+
+// --- 2. OpenAI 响应结构 (非流式) ---
+#[derive(Deserialize, Debug)]
+struct OpenAIResponse {
+    choices: Vec<ResponseChoice>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseChoice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseMessage {
+    content: Option<String>,
+}
+
+// --- 3. OpenAI 响应结构 (流式 Delta) ---
+#[derive(Deserialize, Debug)]
+struct OpenAIStreamResponse {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamChoice {
+    delta: StreamDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAIUsage {
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+}
+
+impl From<OpenAIUsage> for TokenUsage {
+    fn from(usage: OpenAIUsage) -> Self {
+        TokenUsage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamDelta {
+    content: Option<String>, // 注意：内容可能是 None (例如结束包)
+}
+
+// --- 4. OpenAI 错误结构 ---
+#[derive(Deserialize, Debug)]
+struct OpenAIErrorResponse {
+    error: OpenAIErrorBody,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAIErrorBody {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+// OpenAI 以及所有兼容 OpenAI 接口的服务 (DeepSeek、通义、各种中转等)
+pub struct OpenAIProvider;
+
+impl ChatProvider for OpenAIProvider {
+    fn build_request(
+        &self,
+        client: &Client,
+        config: &ModelConfig,
+        messages: &[ChatMessage],
+        stream: bool,
+    ) -> RequestBuilder {
+        let params = &config.params;
+        let max_tokens = config.max_output_tokens();
+        // 推理模型只接受默认的采样参数，传了会直接报 400
+        let reasoning = config.capabilities.reasoning;
+        let sampling = |value: Option<f32>| value.filter(|_| !reasoning);
+        let request_body = OpenAIRequest {
+            model: &config.model,
+            messages: messages
+                .iter()
+                .map(|msg| OpenAIMessage {
+                    role: msg.role.as_str(),
+                    content: &msg.content,
+                })
+                .collect(),
+            stream,
+            stream_options: stream.then_some(StreamOptions {
+                include_usage: true,
+            }),
+            max_tokens: max_tokens.filter(|_| !reasoning),
+            max_completion_tokens: max_tokens.filter(|_| reasoning),
+            temperature: sampling(params.temperature),
+            top_p: sampling(params.top_p),
+            presence_penalty: sampling(params.presence_penalty),
+            frequency_penalty: sampling(params.frequency_penalty),
+            stop: params.stop_sequences(),
+            seed: params.seed,
+        };
+
+        client
+            .post(&config.base_url)
+            .header("Authorization", format!("Bearer {}", config.api_key))
+            .header("Content-Type", "application/json")
+            .json(&request_body)
+    }
+
+    fn parse_stream_event(&self, data: &str) -> AppResult<Vec<StreamEvent>> {
+        // 检查结束标记
+        if data == "[DONE]" {
+            return Ok(vec![StreamEvent::Done]);
+        }
+
+        // 有些兼容服务会在流里直接返回错误 (要先检查：普通包的字段都是可选的，错误包也能解析成功)
+        if let Ok(error) = serde_json::from_str::<OpenAIErrorResponse>(data) {
+            return Err(AppError::StreamError {
+                kind: error.error.kind,
+                message: error.error.message,
+            });
+        }
+
+        if let Ok(response) = serde_json::from_str::<OpenAIStreamResponse>(data) {
+            let mut events = Vec::new();
+            let usage = response.usage.map(TokenUsage::from);
+
+            // 开启 include_usage 后，最后一个包的 choices 为空，只带用量
+            match response.choices.into_iter().next() {
+                Some(choice) => {
+                    if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                        events.push(StreamEvent::Delta(content));
+                    }
+                    if let Some(reason) = choice.finish_reason {
+                        events.push(StreamEvent::Finish {
+                            stop_reason: Some(reason),
+                            usage,
+                        });
+                    } else if let Some(usage) = usage {
+                        events.push(StreamEvent::Usage(usage));
+                    }
+                }
+                None => events.extend(usage.map(StreamEvent::Usage)),
+            }
+            return Ok(events);
+        }
+
+        Ok(Vec::new())
+    }
+
+    fn parse_response(&self, body: &str) -> AppResult<String> {
+        let response: OpenAIResponse = serde_json::from_str(body)
+            .map_err(|e| AppError::AiError(format!("无法解析模型响应: {}", e)))?;
+
+        response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| AppError::AiError("模型返回了空内容".to_string()))
+    }
+
+    fn parse_error(&self, status: StatusCode, body: &str) -> AppError {
+        match serde_json::from_str::<OpenAIErrorResponse>(body) {
+            Ok(error) => AppError::provider(status, error.error.kind, error.error.message),
+            Err(_) => AppError::provider(status, None, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> AppResult<Vec<StreamEvent>> {
+        OpenAIProvider.parse_stream_event(data)
+    }
+
+    #[test]
+    fn parses_content_delta() {
+        let events =
+            parse(r#"{"choices":[{"delta":{"content":"你好"},"finish_reason":null}]}"#).unwrap();
+        assert!(matches!(events.as_slice(), [StreamEvent::Delta(text)] if text == "你好"));
+    }
+
+    #[test]
+    fn maps_error_payload_to_stream_error() {
+        let result = parse(r#"{"error":{"message":"rate limited","type":"rate_limit_exceeded"}}"#);
+        assert!(
+            matches!(
+                &result,
+                Err(AppError::StreamError { kind: Some(kind), message })
+                    if kind == "rate_limit_exceeded" && message == "rate limited"
+            ),
+            "unexpected result: {:?}",
+            result
+        );
+    }
+}
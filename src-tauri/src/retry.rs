@@ -0,0 +1,85 @@
+// 请求失败后的重试策略 (指数退避)
+//
+// 只在还没收到任何输出时重试：已经推给前端的内容没法收回，
+// 这时候再重试会让同一段回复出现两次。
+
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+// models.max_retries 为空时的默认重试次数 (不含第一次请求)
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
+
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration, // Retry-After 超过这个值就不再重试
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_RETRIES)
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            base_delay: BASE_DELAY,
+            max_delay: MAX_DELAY,
+        }
+    }
+
+    // 第 retry 次重试 (从 1 开始) 前要等多久：服务端给了 Retry-After 就听它的，否则 0.5s、1s、2s ...
+    // Retry-After 比 max_delay 还长时返回 None：提前重试只会再收到一次 429，不如直接报错
+    pub fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        match retry_after {
+            Some(delay) => (delay <= self.max_delay).then_some(delay),
+            None => {
+                let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+                Some(self.base_delay.saturating_mul(factor).min(self.max_delay))
+            }
+        }
+    }
+}
+
+// Retry-After 只支持秒数的写法；HTTP 日期的写法很少见，遇到时按正常退避处理
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+    let seconds = value.trim().parse::<u64>().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        let policy = RetryPolicy::new(10);
+        assert_eq!(policy.delay(1, None), Some(Duration::from_millis(500)));
+        assert_eq!(policy.delay(3, None), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(10, None), Some(MAX_DELAY));
+    }
+
+    #[test]
+    fn respects_retry_after() {
+        let policy = RetryPolicy::default();
+        let retry_after = Some(Duration::from_secs(5));
+        assert_eq!(policy.delay(1, retry_after), retry_after);
+    }
+
+    #[test]
+    fn gives_up_when_retry_after_exceeds_the_cap() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.delay(1, Some(MAX_DELAY + Duration::from_secs(1))),
+            None
+        );
+    }
+}
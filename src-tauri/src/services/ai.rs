@@ -1,72 +1,400 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::entities::{
+    conversation_summaries, conversations, messages, models, personas,
+    prelude::{Conversations, Models},
+};
+use crate::error::AppError;
+use crate::providers::{
+    ChatMessage, ChatRole, GenerationParams, ModelCapabilities, ModelConfig, ProviderKind,
+    StreamEvent, TokenUsage,
+};
+use crate::retry::{parse_retry_after, RetryPolicy};
+use crate::services::chat::{STATUS_CANCELLED, STATUS_COMPLETE, STATUS_ERROR};
+use crate::services::model::ModelService;
+use crate::services::persona::PersonaService;
+use crate::services::session::SessionService;
 use crate::services::settings::SettingsService;
-use crate::state::AppState;
+use crate::services::summary::SummaryService;
+use crate::state::{AppState, GenerationTicket};
+use crate::tokenizer::{
+    ContextBudget, HeuristicTokenizer, Tokenizer, DEFAULT_CONTEXT_WINDOW, REPLY_PRIMING_TOKENS,
+};
 use crate::{error::AppResult, services::chat::ChatService};
 use futures::StreamExt;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Serialize;
 use tauri::{AppHandle, Emitter, Manager};
+use tokio_util::sync::CancellationToken;
 
-// --- 2. OpenAI 请求结构 ---
-#[derive(Serialize)]
-struct OpenAIMessage {
-    role: String,
-    content: String,
+// --- 发送给前端的事件负载 ---
+// 所有事件都带上 session_id 和 generation_id，前端据此只处理当前会话、当前这次生成的输出，
+// 切换会话或者几个会话同时生成时就不会串台
+#[derive(Clone, Serialize, Debug)]
+struct StreamPayload {
+    session_id: i64,
+    generation_id: u64,
+    seq: u64, // 同一次生成内递增，前端可以用来发现丢包或乱序
+    chunk: String,
+    done: bool,
+    message_id: Option<i64>, // done 时为保存下来的 AI 消息 id
 }
 
-#[derive(Serialize)]
-struct OpenAIRequest {
-    model: String,
-    messages: Vec<OpenAIMessage>,
-    stream: bool,
-    // temperature: f32, // 可选
+// 一次生成结束后的汇总信息
+#[derive(Clone, Serialize, Debug)]
+struct CompletePayload {
+    session_id: i64,
+    generation_id: u64,
+    message_id: i64,
+    model: String, // 实际生成回复的模型 (用了备用模型时和设置里的不同)
+    stop_reason: Option<String>,
+    usage: TokenUsage,
+}
+
+// 生成被用户停止 (message_id 为保存下来的部分回复，什么都没生成时为空)
+#[derive(Clone, Serialize, Debug)]
+struct CancelledPayload {
+    session_id: i64,
+    generation_id: u64,
+    message_id: Option<i64>,
 }
 
-// --- 3. OpenAI 响应结构 (流式 Delta) ---
-#[derive(Deserialize, Debug)]
-struct OpenAIStreamResponse {
-    choices: Vec<StreamChoice>,
+// 生成失败 (message_id 为保存下来的失败回复)，错误本身展开成 code / message / details / retryable
+#[derive(Serialize, Debug)]
+struct ErrorPayload<'a> {
+    session_id: i64,
+    generation_id: u64,
+    message_id: Option<i64>,
+    #[serde(flatten)]
+    error: &'a AppError,
 }
 
-#[derive(Deserialize, Debug)]
-struct StreamChoice {
-    delta: StreamDelta,
-    // finish_reason: Option<String>,
+// 请求失败，正在等待重试 (retry 从 1 开始，UI 可以显示 "重试中 (retry/max_retries)")
+#[derive(Serialize, Debug)]
+struct RetryingPayload<'a> {
+    session_id: i64,
+    generation_id: u64,
+    retry: u32,
+    max_retries: u32,
+    delay_ms: u64,
+    #[serde(flatten)]
+    error: &'a AppError,
 }
 
-#[derive(Deserialize, Debug)]
-struct StreamDelta {
-    content: Option<String>, // 注意：内容可能是 None (例如结束包)
+// 主模型失败，换用备用模型 (error 为主模型最后一次失败的原因)
+#[derive(Serialize, Debug)]
+struct ModelFallbackPayload<'a> {
+    session_id: i64,
+    generation_id: u64,
+    from_model: &'a str,
+    to_model: &'a str,
+    to_model_id: Option<i64>,
+    #[serde(flatten)]
+    error: &'a AppError,
 }
 
-// --- 4. 发送给前端的事件负载 ---
 #[derive(Clone, Serialize, Debug)]
-struct StreamPayload {
-    chunk: String,
-    done: bool,
+struct ContextBudgetPayload {
+    session_id: i64,
+    generation_id: u64,
+    budget: ContextBudget,
+}
+
+// 一次生成的事件发送器
+struct GenerationEvents {
+    app: AppHandle,
+    session_id: i64,
+    generation_id: u64,
+    seq: u64,
 }
 
+impl GenerationEvents {
+    fn new(app: AppHandle, session_id: i64, generation_id: u64) -> Self {
+        Self {
+            app,
+            session_id,
+            generation_id,
+            seq: 0,
+        }
+    }
+
+    fn stream(&mut self, chunk: &str, done: bool, message_id: Option<i64>) -> AppResult<()> {
+        self.seq += 1;
+        let payload = StreamPayload {
+            session_id: self.session_id,
+            generation_id: self.generation_id,
+            seq: self.seq,
+            chunk: chunk.to_string(),
+            done,
+            message_id,
+        };
+        self.app.emit("ai-response", &payload)?;
+        Ok(())
+    }
+
+    // 推送一段新生成的文本
+    fn chunk(&mut self, chunk: &str) -> AppResult<()> {
+        self.stream(chunk, false, None)
+    }
+
+    // 通知前端流结束
+    fn done(&mut self, message_id: Option<i64>) -> AppResult<()> {
+        self.stream("", true, message_id)
+    }
+
+    fn context_budget(&self, budget: ContextBudget) -> AppResult<()> {
+        let payload = ContextBudgetPayload {
+            session_id: self.session_id,
+            generation_id: self.generation_id,
+            budget,
+        };
+        self.app.emit("ai-context-budget", &payload)?;
+        Ok(())
+    }
+
+    fn complete(
+        &self,
+        message_id: i64,
+        model: &str,
+        stop_reason: Option<String>,
+        usage: TokenUsage,
+    ) -> AppResult<()> {
+        let payload = CompletePayload {
+            session_id: self.session_id,
+            generation_id: self.generation_id,
+            message_id,
+            model: model.to_string(),
+            stop_reason,
+            usage,
+        };
+        self.app.emit("ai-response-complete", &payload)?;
+        Ok(())
+    }
+
+    fn cancelled(&self, message_id: Option<i64>) -> AppResult<()> {
+        let payload = CancelledPayload {
+            session_id: self.session_id,
+            generation_id: self.generation_id,
+            message_id,
+        };
+        self.app.emit("ai-response-cancelled", &payload)?;
+        Ok(())
+    }
+
+    fn retrying(
+        &self,
+        retry: u32,
+        max_retries: u32,
+        delay: Duration,
+        error: &AppError,
+    ) -> AppResult<()> {
+        let payload = RetryingPayload {
+            session_id: self.session_id,
+            generation_id: self.generation_id,
+            retry,
+            max_retries,
+            delay_ms: delay.as_millis() as u64,
+            error,
+        };
+        self.app.emit("ai-retrying", &payload)?;
+        Ok(())
+    }
+
+    fn model_fallback(
+        &self,
+        from: &ModelConfig,
+        to: &ModelConfig,
+        error: &AppError,
+    ) -> AppResult<()> {
+        let payload = ModelFallbackPayload {
+            session_id: self.session_id,
+            generation_id: self.generation_id,
+            from_model: &from.model,
+            to_model: &to.model,
+            to_model_id: to.id,
+            error,
+        };
+        self.app.emit("ai-model-fallback", &payload)?;
+        Ok(())
+    }
+
+    fn error(&self, message_id: Option<i64>, error: &AppError) -> AppResult<()> {
+        let payload = ErrorPayload {
+            session_id: self.session_id,
+            generation_id: self.generation_id,
+            message_id,
+            error,
+        };
+        self.app.emit("ai-error", &payload)?;
+        Ok(())
+    }
+}
+
+// 一次流式请求的结果
+#[derive(Default)]
+struct StreamOutcome {
+    content: String,
+    stop_reason: Option<String>,
+    usage: TokenUsage,
+    cancelled: bool,
+}
+
+// 会话的生成参数，给前端的参数面板用
+#[derive(Serialize, Debug)]
+pub struct SessionParameters {
+    pub model: GenerationParams,     // 模型上的默认值
+    pub persona: GenerationParams,   // 人设上的默认值
+    pub overrides: GenerationParams, // 会话上的覆盖值
+    pub effective: GenerationParams, // 实际发送的参数
+}
+
+// 开启后，超出上下文窗口的旧消息会被总结成摘要，而不是直接丢弃
+const AUTO_SUMMARY_SETTING: &str = "auto_summary";
+
+// 问答之后自动给还是占位标题的会话起标题 (默认开启)
+pub const AUTO_TITLE_SETTING: &str = "auto_title";
+// 起标题用的模型 (models.id)，可以设成一个便宜的小模型；为空时用会话自己的模型
+pub const TITLE_MODEL_SETTING: &str = "title_model_id";
+
+const TITLE_SYSTEM_PROMPT: &str =
+    "你是一个标题助手。请根据给出的对话内容起一个简短的标题，不超过 15 个字，\
+只输出标题本身，不要加引号和句末标点。";
+const TITLE_EXCERPT_CHARS: usize = 500; // 发给模型的每条消息最多截取的长度
+const TITLE_MAX_CHARS: usize = 30; // 模型返回的标题过长时截断
+
+const SUMMARY_SYSTEM_PROMPT: &str =
+    "你是一个对话摘要助手。请把给出的对话内容总结成一段简洁的摘要，\
+保留关键事实、用户的偏好和需求、已经得出的结论以及尚未解决的问题。只输出摘要本身。";
+
 #[derive(Clone)]
 pub struct AiService {
+    db: DatabaseConnection,
     chat_service: ChatService,         // 直接包含 ChatService
     settings_service: SettingsService, // 注入 SettingsService
+    summary_service: SummaryService,   // 长对话的滚动摘要
+    model_service: ModelService,       // 会话用哪个模型
+    persona_service: PersonaService,   // 会话的人设 (系统提示词)
+    session_service: SessionService,   // 自动起标题后更新会话
+    tokenizer: Arc<dyn Tokenizer>,     // 用于估算上下文长度
 }
 
 impl AiService {
-    pub fn new(chat_service: ChatService, settings_service: SettingsService) -> Self {
+    pub fn new(
+        db: &DatabaseConnection,
+        chat_service: ChatService,
+        settings_service: SettingsService,
+        summary_service: SummaryService,
+        model_service: ModelService,
+        persona_service: PersonaService,
+        session_service: SessionService,
+    ) -> Self {
         Self {
+            db: db.clone(),
             chat_service,
             settings_service,
+            summary_service,
+            model_service,
+            persona_service,
+            session_service,
+            tokenizer: Arc::new(HeuristicTokenizer),
         }
     }
-    pub async fn chat_stream(
-        self,
-        app: AppHandle,
+
+    // 替换默认的启发式分词器
+    pub fn with_tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    // 读取会话当前使用的模型配置
+    async fn resolve_model_config(&self, session_id: i64) -> AppResult<ModelConfig> {
+        let (mut config, _) = self.resolve_primary_model(session_id).await?;
+        config.params = config
+            .params
+            .merge(&self.session_overrides(session_id).await?);
+        Ok(config)
+    }
+
+    // 叠加在模型参数之上的两层参数：人设的默认参数、会话自己设置的覆盖值
+    async fn parameter_layers(
+        &self,
         session_id: i64,
-        prompt: String,
+    ) -> AppResult<(GenerationParams, GenerationParams)> {
+        let conversation = Conversations::find_by_id(session_id).one(&self.db).await?;
+        let persona = self.persona_service.get_session_persona(session_id).await?;
+        Ok((
+            GenerationParams::from_json(persona.and_then(|p| p.parameters).as_deref()),
+            GenerationParams::from_json(conversation.and_then(|c| c.parameters).as_deref()),
+        ))
+    }
+
+    // 会话对模型参数的覆盖 (人设的参数再被会话的参数覆盖)
+    async fn session_overrides(&self, session_id: i64) -> AppResult<GenerationParams> {
+        let (persona, overrides) = self.parameter_layers(session_id).await?;
+        Ok(persona.merge(&overrides))
+    }
+
+    // 会话当前使用的生成参数：模型默认值、人设默认值、会话覆盖值和最终合并的结果
+    pub async fn session_parameters(&self, session_id: i64) -> AppResult<SessionParameters> {
+        let (config, _) = self.resolve_primary_model(session_id).await?;
+        let (persona, overrides) = self.parameter_layers(session_id).await?;
+        Ok(SessionParameters {
+            effective: config.params.merge(&persona).merge(&overrides),
+            model: config.params,
+            persona,
+            overrides,
+        })
+    }
+
+    // 校验要设置的会话参数：和模型、人设的参数合并后要符合会话主模型接口的限制
+    pub async fn validate_session_overrides(
+        &self,
+        session_id: i64,
+        overrides: &GenerationParams,
     ) -> AppResult<()> {
-        let client = Client::new();
+        let (config, _) = self.resolve_primary_model(session_id).await?;
+        let (persona, _) = self.parameter_layers(session_id).await?;
+        config
+            .params
+            .merge(&persona)
+            .merge(overrides)
+            .validate_for(config.provider)
+    }
 
-        // 1. 动态读取配置
+    // 会话的主模型配置，以及它在 models 表里对应的行
+    // 顺序：会话指定的模型 -> 人设指定的模型 -> 默认模型 -> settings 里的旧配置 (这时行可能为空)
+    async fn resolve_primary_model(
+        &self,
+        session_id: i64,
+    ) -> AppResult<(ModelConfig, Option<models::Model>)> {
+        let conversation = Conversations::find_by_id(session_id).one(&self.db).await?;
+        let model_id = match conversation.and_then(|conversation| conversation.model_id) {
+            Some(model_id) => Some(model_id),
+            None => self
+                .persona_service
+                .get_session_persona(session_id)
+                .await?
+                .and_then(|persona| persona.model_id),
+        };
+        let mut row = match model_id {
+            Some(model_id) => self.model_service.get_model(model_id).await?,
+            None => None,
+        };
+        if row.is_none() {
+            row = self.model_service.get_default_model().await?;
+        }
+        if let Some(row) = row {
+            let config = Self::row_config(&row)?;
+            return Ok((config, Some(row)));
+        }
+
+        self.resolve_legacy_model().await
+    }
+
+    // 旧版本只在 settings 里存了一组 model / base_url / api_key
+    async fn resolve_legacy_model(&self) -> AppResult<(ModelConfig, Option<models::Model>)> {
         let api_key = self.settings_service.get_setting("api_key", "").await;
         let base_url = self
             .settings_service
@@ -77,101 +405,843 @@ impl AiService {
             .get_setting("model", "gpt-3.5-turbo")
             .await;
 
-        //配置api key
-        if api_key.is_empty() {
-            // 可以在这里 emit 一个错误事件告诉前端“请先配置 API Key”
-            let res = app.emit("need-api-key", "需要apikey").unwrap();
-            eprintln!("API Key is missing!");
-            return Ok(res);
+        // models 表里同名的模型上带着协议和上下文窗口
+        let row = Models::find()
+            .filter(models::Column::ModelId.eq(&model))
+            .one(&self.db)
+            .await?;
+
+        let config = Self::build_config(row.as_ref(), api_key, base_url, model)?;
+        Ok((config, row))
+    }
+
+    // models 表里的一行对应的配置 (地址和 Key 都用这一行自己的)
+    fn row_config(row: &models::Model) -> AppResult<ModelConfig> {
+        Self::build_config(
+            Some(row),
+            row.api_key.clone().unwrap_or_default(),
+            row.base_url.clone(),
+            row.model_id.clone(),
+        )
+    }
+
+    // 协议、上下文窗口、重试次数和模型能力取自 models 表，没有对应的行时用默认值
+    fn build_config(
+        row: Option<&models::Model>,
+        api_key: String,
+        base_url: String,
+        model: String,
+    ) -> AppResult<ModelConfig> {
+        let provider = match row {
+            Some(row) => ProviderKind::parse(&row.provider)?,
+            None => ProviderKind::OpenAI,
+        };
+        let retry = row
+            .and_then(|row| row.max_retries)
+            .map(|retries| RetryPolicy::new(retries.max(0) as u32))
+            .unwrap_or_default();
+        let context_window = row
+            .and_then(|row| row.context_window)
+            .filter(|window| *window > 0)
+            .map(|window| window as usize)
+            .unwrap_or(DEFAULT_CONTEXT_WINDOW);
+        let capabilities = row
+            .map(|row| ModelCapabilities {
+                max_output_tokens: row
+                    .max_output_tokens
+                    .filter(|tokens| *tokens > 0)
+                    .map(|tokens| tokens as u32),
+                supports_vision: row.supports_vision,
+                supports_tools: row.supports_tools,
+                supports_streaming: row.supports_streaming,
+                supports_system_role: row.supports_system_role,
+                reasoning: row.reasoning,
+            })
+            .unwrap_or_default();
+
+        Ok(ModelConfig {
+            id: row.map(|row| row.id),
+            provider,
+            api_key,
+            base_url,
+            model,
+            context_window,
+            retry,
+            capabilities,
+            params: GenerationParams::from_json(row.and_then(|row| row.parameters.as_deref())),
+        })
+    }
+
+    // 按顺序排好的模型链：主模型在前，后面是它的备用模型 (不存在或者配置有误的跳过)
+    async fn resolve_model_chain(&self, session_id: i64) -> AppResult<Vec<ModelConfig>> {
+        let (primary, row) = self.resolve_primary_model(session_id).await?;
+        let fallback_ids: Vec<i64> = row
+            .and_then(|row| row.fallback_model_ids)
+            .and_then(|ids| serde_json::from_str(&ids).ok())
+            .unwrap_or_default();
+
+        let mut chain = vec![primary];
+        for id in fallback_ids {
+            if chain.iter().any(|config| config.id == Some(id)) {
+                continue;
+            }
+            let Some(row) = Models::find_by_id(id).one(&self.db).await? else {
+                continue;
+            };
+            // 备用模型只用自己行上的地址和 Key，不会把主模型的 Key 发给别的服务
+            match Self::row_config(&row) {
+                Ok(config) => chain.push(config),
+                Err(e) => eprintln!("跳过备用模型 {}: {}", row.name, e),
+            }
         }
 
-        // 构造请求体
-        let request_body = OpenAIRequest {
-            model: model.to_string(),
-            messages: vec![OpenAIMessage {
-                role: "user".to_string(),
-                content: prompt, // 这里简化了，实际应该把历史记录 history 传进来
-            }],
-            stream: true,
-        };
-
-        // 发起请求
-        let mut stream = client
-            .post(base_url)
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
+        // 会话上的参数覆盖对备用模型同样生效
+        let overrides = self.session_overrides(session_id).await?;
+        for config in &mut chain {
+            config.params = config.params.merge(&overrides);
+        }
+        Ok(chain)
+    }
+
+    // 按 token 预算裁剪上下文：system 消息和最新一条用户消息一定保留，
+    // 其余消息从最新往最旧放，放不下的 (也就是最旧的几轮) 直接丢弃
+    fn fit_to_context(
+        &self,
+        messages: Vec<ChatMessage>,
+        mut budget: ContextBudget,
+    ) -> (Vec<ChatMessage>, ContextBudget) {
+        let last_user = messages.iter().rposition(|msg| msg.role == ChatRole::User);
+        let costs: Vec<usize> = messages
+            .iter()
+            .map(|msg| self.count_message_tokens(msg))
+            .collect();
+
+        let mut keep = vec![false; messages.len()];
+        let mut used = REPLY_PRIMING_TOKENS;
+        for (i, msg) in messages.iter().enumerate() {
+            if msg.role == ChatRole::System || Some(i) == last_user {
+                keep[i] = true;
+                used += costs[i];
+            }
+        }
+
+        for i in (0..messages.len()).rev() {
+            if keep[i] {
+                continue;
+            }
+            // 保证留下来的是连续的最近几轮，中间不会出现空洞
+            if used + costs[i] > budget.limit_tokens {
+                break;
+            }
+            keep[i] = true;
+            used += costs[i];
+        }
+
+        let total = messages.len();
+        let kept: Vec<ChatMessage> = messages
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(msg, keep)| keep.then_some(msg))
+            .collect();
+
+        budget.used_tokens = used;
+        budget.dropped_messages = total - kept.len();
+        (kept, budget)
+    }
+
+    // 计算会话当前的上下文占用 (不发起请求，也不会生成新的摘要)
+    pub async fn context_budget(&self, session_id: i64) -> AppResult<ContextBudget> {
+        let config = self.resolve_model_config(session_id).await?;
+        let (_, budget) = self
+            .prepare_context(&Client::new(), &config, session_id, None, false)
+            .await?;
+        Ok(budget)
+    }
+
+    fn count_message_tokens(&self, msg: &ChatMessage) -> usize {
+        self.tokenizer
+            .count_message_tokens(msg.role.as_str(), &msg.content)
+    }
+
+    fn to_chat_message(msg: &messages::Model) -> ChatMessage {
+        ChatMessage::new(ChatRole::from_stored(&msg.role), msg.content.clone())
+    }
+
+    // 读取会话当前分支上的历史消息 (按时间顺序)，reply_to 不为空时只取到这条用户消息为止
+    async fn load_history(
+        &self,
+        session_id: i64,
+        reply_to: Option<i64>,
+    ) -> AppResult<Vec<messages::Model>> {
+        let path = match reply_to {
+            Some(message_id) => self.chat_service.get_path(session_id, message_id).await?,
+            None => self.chat_service.get_active_path(session_id).await?,
+        };
+        let history = path
+            .into_iter()
+            // 跳过空内容和生成失败的回复，它们不该作为上下文发给模型
+            .filter(|msg| !msg.content.trim().is_empty() && msg.status != STATUS_ERROR)
+            .collect();
+        Ok(history)
+    }
+
+    async fn summary_enabled(&self) -> bool {
+        self.settings_service
+            .get_setting(AUTO_SUMMARY_SETTING, "false")
+            .await
+            == "true"
+    }
+
+    fn persona_message(persona: &personas::Model) -> ChatMessage {
+        ChatMessage::new(ChatRole::System, persona.system_prompt.clone())
+    }
+
+    fn summary_message(summary: &conversation_summaries::Model) -> ChatMessage {
+        ChatMessage::new(
+            ChatRole::System,
+            format!("以下是之前对话的摘要：\n{}", summary.content),
+        )
+    }
+
+    // 组装发给模型的上下文：
+    // 开启摘要时，已被摘要覆盖的消息换成一条摘要，上下文仍然放不下就把最旧的一段继续总结进去；
+    // 最后再按 token 预算裁剪一遍兜底
+    async fn prepare_context(
+        &self,
+        client: &Client,
+        config: &ModelConfig,
+        session_id: i64,
+        reply_to: Option<i64>,
+        allow_summarize: bool,
+    ) -> AppResult<(Vec<ChatMessage>, ContextBudget)> {
+        let history = self.load_history(session_id, reply_to).await?;
+        // 人设的系统提示词永远放在最前面
+        let persona = self.persona_service.get_session_persona(session_id).await?;
+        let mut messages: Vec<ChatMessage> = persona.iter().map(Self::persona_message).collect();
+
+        if !self.summary_enabled().await {
+            messages.extend(history.iter().map(Self::to_chat_message));
+            return Ok(self.fit_to_context(messages, config.context_budget()));
+        }
+
+        // 别的分支上生成的摘要不能用
+        let path_ids: Vec<i64> = history.iter().map(|msg| msg.id).collect();
+        let mut summary = self
+            .summary_service
+            .get_latest(session_id, &path_ids)
+            .await?;
+        let pending = Self::unsummarized(&history, summary.as_ref());
+
+        if allow_summarize {
+            let covered = self.messages_to_summarize(pending, summary.as_ref(), config);
+            if !covered.is_empty() {
+                let result = self
+                    .summarize(client, config, session_id, summary.as_ref(), covered)
+                    .await;
+                match result {
+                    Ok(saved) => summary = Some(saved),
+                    // 摘要失败不影响本次回答，退化成直接裁剪
+                    Err(e) => eprintln!("生成摘要失败: {}", e),
+                }
+            }
+        }
+
+        messages.extend(summary.iter().map(Self::summary_message));
+        messages.extend(
+            Self::unsummarized(&history, summary.as_ref())
+                .iter()
+                .map(Self::to_chat_message),
+        );
+        Ok(self.fit_to_context(messages, config.context_budget()))
+    }
+
+    // 还没有被摘要覆盖的消息
+    fn unsummarized<'a>(
+        history: &'a [messages::Model],
+        summary: Option<&conversation_summaries::Model>,
+    ) -> &'a [messages::Model] {
+        let start = summary
+            .map(|s| history.partition_point(|msg| msg.id <= s.last_message_id))
+            .unwrap_or(0);
+        &history[start..]
+    }
+
+    // 挑出需要总结的最旧一段消息：上下文放得下就不总结；
+    // 放不下时一直总结到剩余部分只占预算的一半，给后续几轮留出空间，避免每轮都重新总结。
+    // 最新一条用户消息永远不会被总结进去
+    fn messages_to_summarize<'a>(
+        &self,
+        pending: &'a [messages::Model],
+        summary: Option<&conversation_summaries::Model>,
+        config: &ModelConfig,
+    ) -> &'a [messages::Model] {
+        let limit = config.context_budget().limit_tokens;
+        let costs: Vec<usize> = pending
+            .iter()
+            .map(|msg| self.count_message_tokens(&Self::to_chat_message(msg)))
+            .collect();
+        let summary_cost = summary
+            .map(|s| self.count_message_tokens(&Self::summary_message(s)))
+            .unwrap_or(0);
+
+        let mut remaining = REPLY_PRIMING_TOKENS + summary_cost + costs.iter().sum::<usize>();
+        if remaining <= limit {
+            return &[];
+        }
+
+        let last_user = pending
+            .iter()
+            .rposition(|msg| ChatRole::from_stored(&msg.role) == ChatRole::User)
+            .unwrap_or(pending.len());
+        let mut end = 0;
+        while end < last_user && remaining > limit / 2 {
+            remaining -= costs[end];
+            end += 1;
+        }
+        &pending[..end]
+    }
+
+    // 把旧摘要和新一段消息合并成新的摘要并保存
+    async fn summarize(
+        &self,
+        client: &Client,
+        config: &ModelConfig,
+        session_id: i64,
+        previous: Option<&conversation_summaries::Model>,
+        covered: &[messages::Model],
+    ) -> AppResult<conversation_summaries::Model> {
+        let last_message_id = match covered.last() {
+            Some(msg) => msg.id,
+            None => return Err(AppError::AiError("没有需要总结的消息".to_string())),
+        };
+
+        let mut transcript = String::new();
+        if let Some(previous) = previous {
+            transcript.push_str(&format!("之前的摘要：\n{}\n\n", previous.content));
+        }
+        transcript.push_str("新的对话内容：\n");
+        for msg in covered {
+            let role = ChatRole::from_stored(&msg.role);
+            transcript.push_str(&format!("{}: {}\n", role.as_str(), msg.content));
+        }
+
+        let content = self
+            .complete(
+                client,
+                config,
+                vec![
+                    ChatMessage::new(ChatRole::System, SUMMARY_SYSTEM_PROMPT),
+                    ChatMessage::new(ChatRole::User, transcript),
+                ],
+            )
+            .await?;
+
+        self.summary_service
+            .save_summary(session_id, last_message_id, content.trim())
+            .await
+    }
+
+    // 非流式请求，直接拿到完整回复
+    async fn complete(
+        &self,
+        client: &Client,
+        config: &ModelConfig,
+        messages: Vec<ChatMessage>,
+    ) -> AppResult<String> {
+        let provider = config.provider.provider();
+        let messages = config.adapt_messages(&messages);
+        let response = provider
+            .build_request(client, config, &messages, false)
             .send()
-            .await?
-            .bytes_stream();
+            .await?;
+
+        let status = response.status();
+        let retry_after = parse_retry_after(response.headers());
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(provider
+                .parse_error(status, &body)
+                .with_retry_after(retry_after));
+        }
+        provider.parse_response(&body)
+    }
 
-        let mut full_response = String::new();
+    // 发起流式请求并把增量推给前端，取消令牌触发时立即停止
+    // 结果累加在 outcome 里，中途出错时调用方仍能拿到已经生成的部分
+    async fn stream_response(
+        &self,
+        events: &mut GenerationEvents,
+        client: &Client,
+        config: &ModelConfig,
+        messages: &[ChatMessage],
+        cancel: &CancellationToken,
+        outcome: &mut StreamOutcome,
+    ) -> AppResult<()> {
+        // 发起请求 (请求格式由模型对应的 provider 决定)，等待响应期间也可以被停止
+        let provider = config.provider.provider();
+        let messages = config.adapt_messages(messages);
+        let request = provider
+            .build_request(client, config, &messages, true)
+            .send();
+        let response = tokio::select! {
+            _ = cancel.cancelled() => {
+                outcome.cancelled = true;
+                return Ok(());
+            }
+            response = request => response?,
+        };
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = parse_retry_after(response.headers());
+            let body = response.text().await?;
+            return Err(provider
+                .parse_error(status, &body)
+                .with_retry_after(retry_after));
+        }
+        let mut stream = response.bytes_stream();
+
+        // SSE / NDJSON 都先按字节缓冲，凑齐完整的一条再解析
+        let mut decoder = PayloadDecoder::new(provider.stream_format());
 
         // 处理流式响应
-        while let Some(item) = stream.next().await {
-            match item {
-                Ok(bytes) => {
-                    let chunk_str = String::from_utf8_lossy(&bytes);
-
-                    // OpenAI 的流可能会一次返回多行，也可能被截断，这里做简单的按行处理
-                    for line in chunk_str.lines() {
-                        let line = line.trim();
-
-                        // 忽略空行和保活注释
-                        if line.is_empty() || !line.starts_with("data: ") {
-                            continue;
-                        }
+        'stream: loop {
+            let next = tokio::select! {
+                _ = cancel.cancelled() => {
+                    outcome.cancelled = true;
+                    break;
+                }
+                next = stream.next() => next,
+            };
 
-                        // 去掉 "data: " 前缀
-                        let json_str = &line[6..];
+            let (payloads, ended) = match next {
+                Some(Ok(bytes)) => (decoder.push(&bytes), false),
+                // 连接中途断开
+                Some(Err(e)) => return Err(e.into()),
+                // 连接正常关闭，把缓冲区里剩下的也处理掉
+                None => (decoder.finish(), true),
+            };
 
-                        // 检查结束标记
-                        if json_str == "[DONE]" {
-                            break;
-                        }
+            // 交给 provider 解析
+            for data in payloads {
+                for event in provider.parse_stream_event(&data)? {
+                    match event {
+                        StreamEvent::Delta(content) => {
+                            // 1. 推送给前端
+                            events.chunk(&content)?;
 
-                        // 解析 JSON
-                        if let Ok(response) = serde_json::from_str::<OpenAIStreamResponse>(json_str)
-                        {
-                            if let Some(choice) = response.choices.first() {
-                                if let Some(content) = &choice.delta.content {
-                                    // 1. 推送给前端
-                                    let payload = StreamPayload {
-                                        chunk: content.clone(),
-                                        done: false, // 流还没真正结束
-                                    };
-                                    app.emit("ai-response", &payload).unwrap();
-
-                                    // 2. 累加
-                                    full_response.push_str(content);
-                                }
+                            // 2. 累加
+                            outcome.content.push_str(&content);
+                        }
+                        StreamEvent::Usage(reported) => outcome.usage.merge(reported),
+                        StreamEvent::Finish {
+                            stop_reason: reason,
+                            usage: reported,
+                        } => {
+                            outcome.stop_reason = reason.or(outcome.stop_reason);
+                            if let Some(reported) = reported {
+                                outcome.usage.merge(reported);
                             }
                         }
+                        // 检查结束标记 (直接结束整个流，而不只是当前这一批数据)
+                        StreamEvent::Done => break 'stream,
+                        StreamEvent::Ignore => {}
                     }
                 }
-                Err(e) => eprintln!("Stream error: {}", e),
+            }
+
+            if ended {
+                break;
             }
         }
 
-        // --- 流结束处理 ---
+        Ok(())
+    }
 
-        // 保存 AI 的完整回复到数据库
-        self.chat_service
-            .save_message(session_id, "AI", &full_response)
-            .await?;
+    // 非流式请求 (等待期间同样可以被停止)
+    async fn respond_at_once(
+        &self,
+        events: &mut GenerationEvents,
+        client: &Client,
+        config: &ModelConfig,
+        messages: &[ChatMessage],
+        cancel: &CancellationToken,
+        outcome: &mut StreamOutcome,
+    ) -> AppResult<()> {
+        let content = tokio::select! {
+            _ = cancel.cancelled() => {
+                outcome.cancelled = true;
+                return Ok(());
+            }
+            content = self.complete(client, config, messages.to_vec()) => content?,
+        };
 
-        // 通知前端结束
-        app.emit(
-            "ai-response",
-            &StreamPayload {
-                chunk: "".to_string(),
-                done: true,
-            },
-        )
-        .unwrap();
+        events.chunk(&content)?;
+        outcome.content = content;
+        Ok(())
+    }
+
+    // 按模型的重试策略发起流式请求：只要还没收到任何输出，可重试的错误 (网络、429、5xx) 就退避后重来
+    async fn stream_with_retry(
+        &self,
+        events: &mut GenerationEvents,
+        client: &Client,
+        config: &ModelConfig,
+        messages: &[ChatMessage],
+        cancel: &CancellationToken,
+        outcome: &mut StreamOutcome,
+    ) -> AppResult<()> {
+        let policy = config.retry;
+        let mut retry = 0;
+        loop {
+            // 不支持流式的模型退回普通请求，拿到完整回复后一次性推给前端
+            let result = if config.capabilities.supports_streaming {
+                self.stream_response(events, client, config, messages, cancel, outcome)
+                    .await
+            } else {
+                self.respond_at_once(events, client, config, messages, cancel, outcome)
+                    .await
+            };
+            let error = match result {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+
+            if retry >= policy.max_retries || !error.retryable() || !outcome.content.is_empty() {
+                return Err(error);
+            }
+            retry += 1;
+
+            // 服务端要求等的时间太长就不重试了，直接报错 (可以换备用模型)
+            let Some(delay) = policy.delay(retry, error.retry_after()) else {
+                return Err(error);
+            };
+            eprintln!(
+                "请求失败，{:?} 后重试 ({}/{}): {}",
+                delay, retry, policy.max_retries, error
+            );
+            events.retrying(retry, policy.max_retries, delay, &error)?;
+
+            // 等待期间也可以被停止
+            tokio::select! {
+                _ = cancel.cancelled() => {
+                    outcome.cancelled = true;
+                    return Ok(());
+                }
+                _ = tokio::time::sleep(delay) => {}
+            }
+            *outcome = StreamOutcome::default();
+        }
+    }
+
+    // 失败时会保存一条 error 状态的回复，并通过 "ai-error" 通知前端
+    pub async fn chat_stream(
+        self,
+        app: AppHandle,
+        session_id: i64,
+        reply_to: i64,
+        ticket: GenerationTicket,
+    ) -> AppResult<()> {
+        let mut events = GenerationEvents::new(app.clone(), session_id, ticket.id);
+        let mut outcome = StreamOutcome::default();
+
+        let result = self
+            .generate(
+                &app,
+                &mut events,
+                session_id,
+                reply_to,
+                &ticket,
+                &mut outcome,
+            )
+            .await;
+        match result {
+            Ok(()) => {
+                self.spawn_auto_title(app, session_id);
+                Ok(())
+            }
+            Err(error) => {
+                self.fail_generation(&mut events, session_id, reply_to, &outcome.content, &error)
+                    .await;
+                Err(error)
+            }
+        }
+    }
 
-        app.emit("ai-response-complete", ()).unwrap();
+    // 问答完成后在后台起标题，不耽误这次生成结束 (失败只记日志，下一轮问答后再试)
+    // 只有标题还是占位标题时才起，用户改过名或者已经起过标题的不动
+    fn spawn_auto_title(self, app: AppHandle, session_id: i64) {
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = self.auto_title(&app, session_id).await {
+                eprintln!("自动生成标题失败: {}", e);
+            }
+        });
+    }
+
+    async fn auto_title(&self, app: &AppHandle, session_id: i64) -> AppResult<()> {
+        let enabled = self
+            .settings_service
+            .get_setting(AUTO_TITLE_SETTING, "true")
+            .await
+            == "true";
+        let conversation = Conversations::find_by_id(session_id).one(&self.db).await?;
+        if !enabled || !conversation.is_some_and(|c| c.title_is_auto) {
+            return Ok(());
+        }
 
+        let title = self.suggest_title(session_id).await?;
+        if let Some(session) = self
+            .session_service
+            .set_auto_title(session_id, &title)
+            .await?
+        {
+            app.emit("session-updated", &session)?;
+        }
         Ok(())
     }
+
+    // 手动重新生成标题，保存后发 "session-updated" 通知前端
+    pub async fn generate_title(
+        &self,
+        app: &AppHandle,
+        session_id: i64,
+    ) -> AppResult<conversations::Model> {
+        let title = self.suggest_title(session_id).await?;
+        let session = self
+            .session_service
+            .set_session_title(session_id, &title)
+            .await?;
+        app.emit("session-updated", &session)?;
+        Ok(session)
+    }
+
+    // 根据当前分支开头的一问一答让模型起一个标题
+    async fn suggest_title(&self, session_id: i64) -> AppResult<String> {
+        let history = self.load_history(session_id, None).await?;
+        if history.is_empty() {
+            return Err(AppError::ValidationError(
+                "会话里还没有消息，无法生成标题".to_string(),
+            ));
+        }
+
+        let mut transcript = String::new();
+        for msg in history.iter().take(2) {
+            let role = ChatRole::from_stored(&msg.role);
+            let excerpt: String = msg.content.chars().take(TITLE_EXCERPT_CHARS).collect();
+            transcript.push_str(&format!("{}: {}\n", role.as_str(), excerpt));
+        }
+
+        let config = self.title_model_config(session_id).await?;
+        let content = self
+            .complete(
+                &Client::new(),
+                &config,
+                vec![
+                    ChatMessage::new(ChatRole::System, TITLE_SYSTEM_PROMPT),
+                    ChatMessage::new(ChatRole::User, transcript),
+                ],
+            )
+            .await?;
+        Self::clean_title(&content).ok_or_else(|| AppError::AiError("模型没有返回标题".to_string()))
+    }
+
+    // 设置了专门起标题的模型就用它，否则用会话自己的模型
+    async fn title_model_config(&self, session_id: i64) -> AppResult<ModelConfig> {
+        let title_model_id: Option<i64> = self
+            .settings_service
+            .get_setting(TITLE_MODEL_SETTING, "")
+            .await
+            .parse()
+            .ok();
+        if let Some(id) = title_model_id {
+            if let Some(row) = self.model_service.get_model(id).await? {
+                return Self::row_config(&row);
+            }
+        }
+        self.resolve_model_config(session_id).await
+    }
+
+    // 模型不一定听话：只取第一行，去掉 "标题：" 前缀、引号和句末标点，过长时截断
+    fn clean_title(raw: &str) -> Option<String> {
+        let line = raw.lines().map(str::trim).find(|line| !line.is_empty())?;
+        let line = line
+            .strip_prefix("标题：")
+            .or_else(|| line.strip_prefix("标题:"))
+            .unwrap_or(line);
+        let title: String = line
+            .trim_matches(|c: char| c.is_whitespace() || "\"'“”‘’「」《》*#。.".contains(c))
+            .chars()
+            .take(TITLE_MAX_CHARS)
+            .collect();
+        (!title.is_empty()).then_some(title)
+    }
+
+    // 保存失败的回复并通知前端 (保存本身失败时也要让前端停止等待)
+    async fn fail_generation(
+        &self,
+        events: &mut GenerationEvents,
+        session_id: i64,
+        reply_to: i64,
+        partial: &str,
+        error: &AppError,
+    ) {
+        // 会话已经被清空或删除，没有地方保存，也不用再报错
+        if !self.reply_target_exists(reply_to).await {
+            if let Err(e) = events.done(None) {
+                eprintln!("发送 ai-done 失败: {}", e);
+            }
+            return;
+        }
+
+        let message_id = match self
+            .chat_service
+            .save_failed_message(session_id, reply_to, partial, &error.to_string())
+            .await
+        {
+            Ok(saved) => Some(saved.id),
+            Err(e) => {
+                eprintln!("保存失败的回复出错: {}", e);
+                None
+            }
+        };
+
+        // 前端可能已经关闭，通知不到也只能记日志
+        if let Err(e) = events
+            .done(message_id)
+            .and_then(|_| events.error(message_id, error))
+        {
+            eprintln!("发送 ai-error 失败: {}", e);
+        }
+    }
+
+    async fn generate(
+        &self,
+        app: &AppHandle,
+        events: &mut GenerationEvents,
+        session_id: i64,
+        reply_to: i64,
+        ticket: &GenerationTicket,
+        outcome: &mut StreamOutcome,
+    ) -> AppResult<()> {
+        let client = Client::new();
+
+        // 1. 动态读取配置 (主模型 + 备用模型)
+        let chain = self.resolve_model_chain(session_id).await?;
+
+        //配置api key (缺少时提示前端去设置，这次生成按失败处理，配置好之后可以重试)
+        let primary = &chain[0];
+        if primary.provider.requires_api_key() && primary.api_key.is_empty() {
+            app.emit("need-api-key", "需要apikey")?;
+            return Err(AppError::MissingConfig("api_key".to_string()));
+        }
+
+        // 2. 依次尝试：主模型重试用完或者遇到不可重试的服务端错误时，换下一个模型
+        let mut failed: Option<(&ModelConfig, AppError)> = None;
+        for (index, config) in chain.iter().enumerate() {
+            if config.provider.requires_api_key() && config.api_key.is_empty() {
+                continue;
+            }
+            // 人设、会话上的参数可能不符合这个模型接口的限制，发出去之前先拦下来
+            // 主模型不符合直接报错；备用模型不符合就跳过，不要盖掉主模型真正的错误
+            if let Err(error) = config.params.validate_for(config.provider) {
+                if index == 0 {
+                    return Err(error);
+                }
+                eprintln!("跳过备用模型 {}: {}", config.model, error);
+                continue;
+            }
+            if let Some((from, error)) = &failed {
+                events.model_fallback(from, config, error)?;
+                *outcome = StreamOutcome::default();
+            }
+
+            // 构造请求体 (带上会话的历史记录，过长时旧消息会被摘要或裁掉)
+            // 备用模型的上下文窗口可能不同，要重新裁剪；摘要只在主模型这一轮生成
+            let (messages, budget) = self
+                .prepare_context(&client, config, session_id, Some(reply_to), index == 0)
+                .await?;
+            events.context_budget(budget)?;
+
+            match self
+                .stream_with_retry(events, &client, config, &messages, &ticket.cancel, outcome)
+                .await
+            {
+                Ok(()) => {
+                    return self
+                        .finish_generation(events, session_id, reply_to, config, outcome)
+                        .await
+                }
+                // 已经输出了一部分就不能换模型了，否则同一条回复会拼上两个模型的内容
+                Err(error) if error.is_provider_failure() && outcome.content.is_empty() => {
+                    failed = Some((config, error));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        match failed {
+            Some((_, error)) => Err(error),
+            None => Err(AppError::MissingConfig("api_key".to_string())),
+        }
+    }
+
+    // 被回复的消息还在不在 (查询出错时按还在处理，交给后面的保存去报错)
+    async fn reply_target_exists(&self, reply_to: i64) -> bool {
+        !matches!(self.chat_service.get_message(reply_to).await, Ok(None))
+    }
+
+    // 保存回复并通知前端生成结束 (config 为实际生成这条回复的模型)
+    async fn finish_generation(
+        &self,
+        events: &mut GenerationEvents,
+        session_id: i64,
+        reply_to: i64,
+        config: &ModelConfig,
+        outcome: &StreamOutcome,
+    ) -> AppResult<()> {
+        // --- 流结束处理 ---
+
+        // 生成期间会话被清空或删除了 (被问的消息已经不在)，回复不再保存
+        if !self.reply_target_exists(reply_to).await {
+            events.done(None)?;
+            return events.cancelled(None);
+        }
+
+        // 保存 AI 的回复到数据库 (被停止的回复也保存已经生成的部分，并标记为 cancelled)
+        let status = if outcome.cancelled {
+            STATUS_CANCELLED
+        } else {
+            STATUS_COMPLETE
+        };
+        let saved = if !outcome.cancelled || !outcome.content.is_empty() {
+            Some(
+                self.chat_service
+                    .save_reply(
+                        session_id,
+                        reply_to,
+                        &outcome.content,
+                        status,
+                        config.id,
+                        &config.model,
+                    )
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        // 通知前端结束
+        let message_id = saved.map(|msg| msg.id);
+        events.done(message_id)?;
+
+        match message_id {
+            Some(message_id) if !outcome.cancelled => events.complete(
+                message_id,
+                &config.model,
+                outcome.stop_reason.clone(),
+                outcome.usage,
+            ),
+            _ => events.cancelled(message_id),
+        }
+    }
 }
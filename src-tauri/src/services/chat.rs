@@ -1,11 +1,31 @@
+use std::collections::HashMap;
+
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
-    QueryOrder,
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition,
+    DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+use serde::Serialize;
+
+use crate::entities::{
+    conversations, messages,
+    prelude::{Conversations, Messages},
 };
 
-use crate::entities::{messages, prelude::Messages};
+use crate::error::{AppError, AppResult};
 
-use crate::error::AppResult;
+// 消息状态 (messages.status)
+pub const STATUS_COMPLETE: &str = "complete";
+pub const STATUS_CANCELLED: &str = "cancelled"; // 生成到一半被用户停止
+pub const STATUS_ERROR: &str = "error"; // 生成失败，错误详情在 error 字段里
+
+// 历史里的一条消息，附带分支信息：
+// 同一个父消息下的子消息 (编辑、重新生成产生) 互为兄弟，前端据此在分支之间切换
+#[derive(Serialize, Debug)]
+pub struct HistoryMessage {
+    #[serde(flatten)]
+    pub message: messages::Model,
+    pub sibling_ids: Vec<i64>, // 包括自己，按创建顺序；只有一个时没有分支
+}
 
 // 1. 变成一个持有 db 的结构体，并派生 Clone
 #[derive(Clone)]
@@ -17,14 +37,172 @@ impl ChatService {
     pub fn new(db: &DatabaseConnection) -> Self {
         Self { db: db.clone() }
     }
-    // 1. 获取聊天历史记录
-    pub async fn get_history(&self, session_id: i32) -> AppResult<Vec<messages::Model>> {
-        let messages = Messages::find()
-            .filter(messages::Column::ConversationId.eq(session_id))
-            .order_by_asc(messages::Column::CreatedAt) // 按时间正序
-            .all(&self.db)
+    // 1. 获取聊天历史记录 (当前分支，带分支信息)
+    pub async fn get_history(&self, session_id: i64) -> AppResult<Vec<HistoryMessage>> {
+        let messages = self.get_messages_by_session(session_id).await?;
+        let leaf = self.active_leaf_id(session_id).await?;
+
+        let history = Self::path_to(&messages, leaf)
+            .into_iter()
+            .map(|message| {
+                let sibling_ids = messages
+                    .iter()
+                    .filter(|msg| msg.parent_id == message.parent_id)
+                    .map(|msg| msg.id)
+                    .collect();
+                HistoryMessage {
+                    message,
+                    sibling_ids,
+                }
+            })
+            .collect();
+        Ok(history)
+    }
+
+    // 当前分支上的消息 (从根到末端)
+    pub async fn get_active_path(&self, session_id: i64) -> AppResult<Vec<messages::Model>> {
+        let messages = self.get_messages_by_session(session_id).await?;
+        let leaf = self.active_leaf_id(session_id).await?;
+        Ok(Self::path_to(&messages, leaf))
+    }
+
+    // 从根到指定消息的这一段对话
+    pub async fn get_path(
+        &self,
+        session_id: i64,
+        message_id: i64,
+    ) -> AppResult<Vec<messages::Model>> {
+        let messages = self.get_messages_by_session(session_id).await?;
+        Ok(Self::path_to(&messages, Some(message_id)))
+    }
+
+    // 沿 parent_id 从 leaf 往上走到根，再倒过来；
+    // leaf 为空或者已经不存在 (旧数据、被删除) 时退回到最新的一条消息
+    fn path_to(messages: &[messages::Model], leaf: Option<i64>) -> Vec<messages::Model> {
+        let by_id: HashMap<i64, &messages::Model> =
+            messages.iter().map(|msg| (msg.id, msg)).collect();
+        let leaf = leaf
+            .filter(|id| by_id.contains_key(id))
+            .or_else(|| messages.iter().map(|msg| msg.id).max());
+
+        let mut path = Vec::new();
+        let mut next = leaf;
+        while let Some(msg) = next.and_then(|id| by_id.get(&id)) {
+            path.push((*msg).clone());
+            // 父消息一定比子消息先插入，顺便防止脏数据成环
+            next = msg.parent_id.filter(|parent_id| *parent_id < msg.id);
+        }
+        path.reverse();
+        path
+    }
+
+    async fn active_leaf_id(&self, session_id: i64) -> AppResult<Option<i64>> {
+        let conversation = Conversations::find_by_id(session_id).one(&self.db).await?;
+        Ok(conversation.and_then(|conversation| conversation.active_leaf_id))
+    }
+
+    // 当前分支的最后一条消息，新消息接在它后面
+    async fn current_leaf(&self, session_id: i64) -> AppResult<Option<i64>> {
+        let path = self.get_active_path(session_id).await?;
+        Ok(path.last().map(|msg| msg.id))
+    }
+
+    pub async fn set_active_leaf(&self, session_id: i64, message_id: Option<i64>) -> AppResult<()> {
+        Conversations::update_many()
+            .col_expr(conversations::Column::ActiveLeafId, Expr::value(message_id))
+            .filter(conversations::Column::Id.eq(session_id))
+            .exec(&self.db)
             .await?;
-        Ok(messages)
+        Ok(())
+    }
+
+    // 切换到某条消息所在的分支：从它开始一直沿最新的子消息走到底，作为新的末端
+    pub async fn switch_branch(
+        &self,
+        session_id: i64,
+        message_id: i64,
+    ) -> AppResult<Vec<HistoryMessage>> {
+        let messages = self.get_messages_by_session(session_id).await?;
+        if !messages.iter().any(|msg| msg.id == message_id) {
+            return Err(AppError::ValidationError(format!(
+                "消息不存在: {}",
+                message_id
+            )));
+        }
+
+        let mut leaf = message_id;
+        while let Some(child) = messages
+            .iter()
+            .filter(|msg| msg.parent_id == Some(leaf))
+            .map(|msg| msg.id)
+            .max()
+        {
+            leaf = child;
+        }
+
+        self.set_active_leaf(session_id, Some(leaf)).await?;
+        self.get_history(session_id).await
+    }
+
+    // 编辑一条用户消息：原消息和它后面的对话保持不动，
+    // 新内容作为它的兄弟消息保存，开出一条新分支
+    pub async fn edit_message(
+        &self,
+        session_id: i64,
+        message_id: i64,
+        content: &str,
+    ) -> AppResult<messages::Model> {
+        let original = self
+            .get_message(message_id)
+            .await?
+            .filter(|msg| msg.conversation_id == session_id && msg.role == "user")
+            .ok_or_else(|| AppError::ValidationError("只能编辑用户发送的消息".to_string()))?;
+
+        let new_msg = messages::ActiveModel {
+            role: Set(original.role),
+            conversation_id: Set(session_id),
+            content: Set(content.to_string()),
+            status: Set(STATUS_COMPLETE.to_string()),
+            parent_id: Set(original.parent_id),
+            ..Default::default()
+        };
+        self.insert_message(session_id, new_msg).await
+    }
+
+    // 插入一条 AI 回复：只有当前分支的末端还是它回答的那条消息时才把末端移过来。
+    // 生成期间用户切换了分支、或者这次生成已经被新的发送取代时，迟到的回复只作为一个分支保存
+    async fn insert_reply(
+        &self,
+        session_id: i64,
+        parent_id: i64,
+        new_msg: messages::ActiveModel,
+    ) -> AppResult<messages::Model> {
+        let saved_msg = new_msg.insert(&self.db).await?;
+        Conversations::update_many()
+            .col_expr(
+                conversations::Column::ActiveLeafId,
+                Expr::value(saved_msg.id),
+            )
+            .filter(conversations::Column::Id.eq(session_id))
+            .filter(
+                Condition::any()
+                    .add(conversations::Column::ActiveLeafId.eq(parent_id))
+                    .add(conversations::Column::ActiveLeafId.is_null()),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(saved_msg)
+    }
+
+    // 插入一条消息，并把它设为会话当前分支的末端
+    async fn insert_message(
+        &self,
+        session_id: i64,
+        new_msg: messages::ActiveModel,
+    ) -> AppResult<messages::Model> {
+        let saved_msg = new_msg.insert(&self.db).await?;
+        self.set_active_leaf(session_id, Some(saved_msg.id)).await?;
+        Ok(saved_msg)
     }
 
     // 2. 保存一条新消息 (无论是用户发的还是 AI 回的)
@@ -34,31 +212,133 @@ impl ChatService {
         role: &str,
         content: &str,
     ) -> AppResult<messages::Model> {
+        self.save_message_with_status(session_id, role, content, STATUS_COMPLETE)
+            .await
+    }
+
+    // 保存一条带状态的消息 (比如被中途停止的 AI 回复)
+    pub async fn save_message_with_status(
+        &self,
+        session_id: i64,
+        role: &str,
+        content: &str,
+        status: &str,
+    ) -> AppResult<messages::Model> {
+        // 接在当前分支的末端
+        let parent_id = self.current_leaf(session_id).await?;
         let new_msg = messages::ActiveModel {
             role: Set(role.to_string()),
             conversation_id: Set(session_id),
             content: Set(content.to_string()),
+            status: Set(status.to_string()),
+            parent_id: Set(parent_id),
             // created_at 会由数据库默认值自动生成，或者你也可以在这里 Set(Utc::now())
             ..Default::default()
         };
+        self.insert_message(session_id, new_msg).await
+    }
 
-        let saved_msg = new_msg.insert(&self.db).await?;
-        Ok(saved_msg)
+    // 保存一条 AI 回复 (parent_id 为它回答的用户消息)，并记下实际生成它的模型
+    pub async fn save_reply(
+        &self,
+        session_id: i64,
+        parent_id: i64,
+        content: &str,
+        status: &str,
+        model_id: Option<i64>,
+        model_name: &str,
+    ) -> AppResult<messages::Model> {
+        let new_msg = messages::ActiveModel {
+            role: Set("AI".to_string()),
+            conversation_id: Set(session_id),
+            content: Set(content.to_string()),
+            status: Set(status.to_string()),
+            model_id: Set(model_id),
+            model_name: Set(Some(model_name.to_string())),
+            parent_id: Set(Some(parent_id)),
+            ..Default::default()
+        };
+        self.insert_reply(session_id, parent_id, new_msg).await
+    }
+
+    // 保存一条生成失败的 AI 回复 (content 为失败前已经生成的部分)，便于在历史里展示和重试
+    pub async fn save_failed_message(
+        &self,
+        session_id: i64,
+        parent_id: i64,
+        content: &str,
+        error: &str,
+    ) -> AppResult<messages::Model> {
+        let new_msg = messages::ActiveModel {
+            role: Set("AI".to_string()),
+            conversation_id: Set(session_id),
+            content: Set(content.to_string()),
+            status: Set(STATUS_ERROR.to_string()),
+            error: Set(Some(error.to_string())),
+            parent_id: Set(Some(parent_id)),
+            ..Default::default()
+        };
+        self.insert_reply(session_id, parent_id, new_msg).await
+    }
+
+    pub async fn get_message(&self, message_id: i64) -> AppResult<Option<messages::Model>> {
+        let message = Messages::find_by_id(message_id).one(&self.db).await?;
+        Ok(message)
+    }
+
+    // 同一个父消息下的所有版本 (按生成顺序)
+    pub async fn get_versions(&self, parent_id: i64) -> AppResult<Vec<messages::Model>> {
+        let versions = Messages::find()
+            .filter(messages::Column::ParentId.eq(parent_id))
+            .order_by_asc(messages::Column::Id)
+            .all(&self.db)
+            .await?;
+        Ok(versions)
+    }
+
+    // 按 id 删除一条消息 (重试失败的回复前先删掉它)
+    // 它的子消息改挂到它的父消息下；它是当前分支末端时，末端退回到父消息
+    pub async fn delete_message(&self, message_id: i64) -> AppResult<()> {
+        let Some(message) = self.get_message(message_id).await? else {
+            return Ok(());
+        };
+
+        Messages::update_many()
+            .col_expr(messages::Column::ParentId, Expr::value(message.parent_id))
+            .filter(messages::Column::ParentId.eq(message_id))
+            .exec(&self.db)
+            .await?;
+
+        if self.active_leaf_id(message.conversation_id).await? == Some(message_id) {
+            self.set_active_leaf(message.conversation_id, message.parent_id)
+                .await?;
+        }
+
+        Messages::delete_by_id(message_id).exec(&self.db).await?;
+        Ok(())
     }
 
     // 3. 获取指定会话的消息 (不再是获取所有消息)
-    pub async fn get_messages_by_session(&self, session_id: i32) -> AppResult<Vec<messages::Model>> {
+    pub async fn get_messages_by_session(
+        &self,
+        session_id: i64,
+    ) -> AppResult<Vec<messages::Model>> {
         let messages = Messages::find()
             .filter(messages::Column::ConversationId.eq(session_id)) // 过滤条件
             .order_by_asc(messages::Column::CreatedAt)
+            .order_by_asc(messages::Column::Id) // 同一秒内的消息按插入顺序
             .all(&self.db)
             .await?;
         Ok(messages)
     }
 
-    // 3. (预留) 清空历史
-    pub async fn clear_history(&self) -> AppResult<()> {
-        Messages::delete_many().exec(&self.db).await?;
+    // 清空一个会话的所有消息 (摘要通过外键一起删掉)，会话本身保留
+    pub async fn clear_history(&self, session_id: i64) -> AppResult<()> {
+        Messages::delete_many()
+            .filter(messages::Column::ConversationId.eq(session_id))
+            .exec(&self.db)
+            .await?;
+        self.set_active_leaf(session_id, None).await?;
         Ok(())
     }
 }
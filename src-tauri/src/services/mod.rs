@@ -1,13 +1,19 @@
 use sea_orm::DatabaseConnection;
 
 use crate::services::{
-    ai::AiService, chat::ChatService, session::SessionService, settings::SettingsService,
+    ai::AiService, chat::ChatService, model::ModelService, ollama::OllamaService,
+    persona::PersonaService, session::SessionService, settings::SettingsService,
+    summary::SummaryService,
 };
 
 pub mod ai;
 pub mod chat;
+pub mod model;
+pub mod ollama;
+pub mod persona;
 pub mod session;
 pub mod settings;
+pub mod summary;
 
 #[derive(Clone)] // 因为内部字段都实现了 Clone，所以它可以 Clone
 pub struct AppServices {
@@ -15,6 +21,10 @@ pub struct AppServices {
     pub ai: AiService,
     pub settings: SettingsService,
     pub sessions: SessionService,
+    pub summaries: SummaryService,
+    pub ollama: OllamaService,
+    pub models: ModelService,
+    pub personas: PersonaService,
 }
 
 impl AppServices {
@@ -23,16 +33,32 @@ impl AppServices {
         // 在这里处理依赖关系，lib.rs 就不需要关心谁依赖谁了
         let settings = SettingsService::new(db);
         let chat = ChatService::new(db);
-        let sessions = SessionService::new(db);
+        let sessions = SessionService::new(db, chat.clone());
+        let summaries = SummaryService::new(db);
+        let ollama = OllamaService::new(db);
+        let models = ModelService::new(db, settings.clone());
+        let personas = PersonaService::new(db);
 
         // 比如 AI 服务依赖 Chat 和 Settings，在这里组装
-        let ai = AiService::new(chat.clone(), settings.clone());
+        let ai = AiService::new(
+            db,
+            chat.clone(),
+            settings.clone(),
+            summaries.clone(),
+            models.clone(),
+            personas.clone(),
+            sessions.clone(),
+        );
 
         Self {
             chat,
             ai,
             settings,
             sessions,
+            summaries,
+            ollama,
+            models,
+            personas,
         }
     }
 }
@@ -0,0 +1,502 @@
+use reqwest::{Client, Url};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entities::{
+        conversations, models, personas,
+        prelude::{Conversations, Models, Personas},
+    },
+    error::{AppError, AppResult},
+    providers::{GenerationParams, ProviderKind},
+    services::settings::SettingsService,
+};
+
+// 默认模型存在 settings 表里 (值为 models.id)
+pub const DEFAULT_MODEL_SETTING: &str = "default_model_id";
+
+// 前端新建 / 编辑模型时提交的字段
+#[derive(Deserialize, Clone, Debug)]
+pub struct ModelInput {
+    pub name: String,
+    pub model_id: String,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub icon: Option<String>,
+    pub description: Option<String>,
+    #[serde(default = "default_provider")]
+    pub provider: String,
+    pub context_window: Option<i64>,
+    pub max_retries: Option<i64>,
+    #[serde(default)]
+    pub fallback_model_ids: Vec<i64>,
+    // 模型能力，没传时按普通聊天模型处理
+    pub max_output_tokens: Option<i64>,
+    #[serde(default)]
+    pub supports_vision: bool,
+    #[serde(default)]
+    pub supports_tools: bool,
+    #[serde(default = "default_true")]
+    pub supports_streaming: bool,
+    #[serde(default = "default_true")]
+    pub supports_system_role: bool,
+    #[serde(default)]
+    pub reasoning: bool,
+    // 默认生成参数，会话上可以再覆盖
+    #[serde(default)]
+    pub parameters: GenerationParams,
+}
+
+fn default_provider() -> String {
+    ProviderKind::OpenAI.as_str().to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// --- /v1/models 返回的模型列表 (OpenAI 兼容接口) ---
+#[derive(Deserialize)]
+struct ModelListResponse {
+    #[serde(default)]
+    data: Vec<RemoteModel>,
+}
+
+#[derive(Deserialize)]
+struct RemoteModel {
+    id: String,
+    owned_by: Option<String>,
+    created: Option<i64>,
+}
+
+// 远端目录里的一个模型，附带猜出来的显示名和图标，以及是否已经导入过
+#[derive(Serialize, Clone, Debug)]
+pub struct CatalogModel {
+    pub model_id: String,
+    pub name: String,
+    pub icon: String,
+    pub owned_by: Option<String>,
+    pub created: Option<i64>,
+    pub imported: bool,
+}
+
+// 查询目录用的接口地址和 Key (直接填写，或者取自已有的模型行)
+#[derive(Clone, Debug)]
+pub struct CatalogSource {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub provider: String,
+}
+
+#[derive(Clone)]
+pub struct ModelService {
+    db: DatabaseConnection,
+    settings_service: SettingsService,
+    client: Client,
+}
+
+impl ModelService {
+    pub fn new(db: &DatabaseConnection, settings_service: SettingsService) -> Self {
+        Self {
+            db: db.clone(),
+            settings_service,
+            client: Client::new(),
+        }
+    }
+
+    // 1. 模型列表
+    pub async fn list_models(&self) -> AppResult<Vec<models::Model>> {
+        let models = Models::find()
+            .order_by_asc(models::Column::Id)
+            .all(&self.db)
+            .await?;
+        Ok(models)
+    }
+
+    pub async fn get_model(&self, id: i64) -> AppResult<Option<models::Model>> {
+        let model = Models::find_by_id(id).one(&self.db).await?;
+        Ok(model)
+    }
+
+    // 2. 新建模型
+    pub async fn create_model(&self, input: ModelInput) -> AppResult<models::Model> {
+        self.validate(None, &input).await?;
+
+        let mut row = models::ActiveModel::default();
+        Self::apply(&mut row, input)?;
+        let model = row.insert(&self.db).await?;
+        Ok(model)
+    }
+
+    // 3. 修改模型
+    pub async fn update_model(&self, id: i64, input: ModelInput) -> AppResult<models::Model> {
+        let existing = self
+            .get_model(id)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("模型不存在: {}", id)))?;
+        self.validate(Some(id), &input).await?;
+
+        let mut row: models::ActiveModel = existing.into();
+        Self::apply(&mut row, input)?;
+        let model = row.update(&self.db).await?;
+        Ok(model)
+    }
+
+    // 4. 删除模型：用到它的会话改回默认模型，人设不再指定模型，它是默认模型时清掉默认设置
+    pub async fn delete_model(&self, id: i64) -> AppResult<()> {
+        Conversations::update_many()
+            .col_expr(
+                conversations::Column::ModelId,
+                Expr::value(Option::<i64>::None),
+            )
+            .filter(conversations::Column::ModelId.eq(id))
+            .exec(&self.db)
+            .await?;
+
+        Personas::update_many()
+            .col_expr(personas::Column::ModelId, Expr::value(Option::<i64>::None))
+            .filter(personas::Column::ModelId.eq(id))
+            .exec(&self.db)
+            .await?;
+
+        if self.default_model_id().await == Some(id) {
+            self.settings_service
+                .save_setting(DEFAULT_MODEL_SETTING, "")
+                .await?;
+        }
+
+        Models::delete_by_id(id).exec(&self.db).await?;
+        Ok(())
+    }
+
+    // 5. 设置默认模型 (会话没有指定模型时使用)
+    pub async fn set_default_model(&self, id: i64) -> AppResult<models::Model> {
+        let model = self
+            .get_model(id)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("模型不存在: {}", id)))?;
+        self.settings_service
+            .save_setting(DEFAULT_MODEL_SETTING, &id.to_string())
+            .await?;
+        Ok(model)
+    }
+
+    pub async fn default_model_id(&self) -> Option<i64> {
+        self.settings_service
+            .get_setting(DEFAULT_MODEL_SETTING, "")
+            .await
+            .parse()
+            .ok()
+    }
+
+    // 默认模型 (没有设置或者已经被删掉时为空)
+    pub async fn get_default_model(&self) -> AppResult<Option<models::Model>> {
+        match self.default_model_id().await {
+            Some(id) => self.get_model(id).await,
+            None => Ok(None),
+        }
+    }
+
+    // 6. 目录的来源：传了模型行 id 就用那一行的地址和 Key (只支持 OpenAI 兼容的行)，否则用直接填写的
+    pub async fn catalog_source(
+        &self,
+        model_row_id: Option<i64>,
+        base_url: Option<String>,
+        api_key: Option<String>,
+    ) -> AppResult<CatalogSource> {
+        if let Some(id) = model_row_id {
+            let row = self
+                .get_model(id)
+                .await?
+                .ok_or_else(|| AppError::ValidationError(format!("模型不存在: {}", id)))?;
+            // 目录接口和导入出来的 chat/completions 地址都是 OpenAI 的格式，
+            // 其他协议的服务 (Anthropic、Gemini、Ollama) 不能这样导入
+            if ProviderKind::parse(&row.provider)? != ProviderKind::OpenAI {
+                return Err(AppError::ValidationError(format!(
+                    "只能从 OpenAI 兼容的服务导入模型，{} 使用的是 {} 协议",
+                    row.name, row.provider
+                )));
+            }
+            return Ok(CatalogSource {
+                base_url: row.base_url,
+                api_key: row.api_key,
+                provider: row.provider,
+            });
+        }
+
+        let base_url = base_url
+            .filter(|url| !url.trim().is_empty())
+            .ok_or_else(|| AppError::ValidationError("请填写接口地址".to_string()))?;
+        Ok(CatalogSource {
+            base_url,
+            api_key: api_key.filter(|key| !key.trim().is_empty()),
+            provider: default_provider(),
+        })
+    }
+
+    // 7. 调用 OpenAI 兼容的 /models 接口，列出服务上可用的模型
+    pub async fn fetch_catalog(&self, source: &CatalogSource) -> AppResult<Vec<CatalogModel>> {
+        let url = models_url(&source.base_url)?;
+        let mut request = self.client.get(url);
+        if let Some(api_key) = &source.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(AppError::provider(status, None, body));
+        }
+        let list: ModelListResponse = response.json().await?;
+
+        let chat_url = chat_completions_url(&source.base_url)?;
+        let imported = self.imported_model_ids(&chat_url).await?;
+
+        let mut catalog: Vec<CatalogModel> = list
+            .data
+            .into_iter()
+            .map(|remote| CatalogModel {
+                name: guess_model_name(&remote.id),
+                icon: guess_model_icon(&remote.id).to_string(),
+                imported: imported.contains(&remote.id),
+                model_id: remote.id,
+                owned_by: remote.owned_by,
+                created: remote.created,
+            })
+            .collect();
+        catalog.sort_by(|a, b| a.model_id.cmp(&b.model_id));
+        Ok(catalog)
+    }
+
+    // 8. 把选中的模型批量加进 models 表 (已经导入过的跳过)，返回新增的行
+    pub async fn import_models(
+        &self,
+        source: &CatalogSource,
+        model_ids: Vec<String>,
+    ) -> AppResult<Vec<models::Model>> {
+        ProviderKind::parse(&source.provider)?;
+        let chat_url = chat_completions_url(&source.base_url)?;
+        let mut existing = self.imported_model_ids(&chat_url).await?;
+
+        let mut inserted = Vec::new();
+        for model_id in model_ids {
+            let model_id = model_id.trim().to_string();
+            if model_id.is_empty() || existing.contains(&model_id) {
+                continue;
+            }
+
+            let row = models::ActiveModel {
+                name: Set(guess_model_name(&model_id)),
+                model_id: Set(model_id.clone()),
+                base_url: Set(chat_url.clone()),
+                api_key: Set(source.api_key.clone()),
+                icon: Set(Some(guess_model_icon(&model_id).to_string())),
+                provider: Set(source.provider.clone()),
+                ..Default::default()
+            };
+            inserted.push(row.insert(&self.db).await?);
+            existing.push(model_id);
+        }
+        Ok(inserted)
+    }
+
+    // 同一个接口地址下已经导入过的模型 id
+    async fn imported_model_ids(&self, chat_url: &str) -> AppResult<Vec<String>> {
+        let ids = Models::find()
+            .filter(models::Column::BaseUrl.eq(chat_url))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|row| row.model_id)
+            .collect();
+        Ok(ids)
+    }
+
+    async fn validate(&self, id: Option<i64>, input: &ModelInput) -> AppResult<()> {
+        if input.name.trim().is_empty() {
+            return Err(AppError::ValidationError("模型名称不能为空".to_string()));
+        }
+        if input.model_id.trim().is_empty() {
+            return Err(AppError::ValidationError("模型 ID 不能为空".to_string()));
+        }
+        let provider = ProviderKind::parse(&input.provider)?;
+        validate_base_url(&input.base_url)?;
+
+        if input.context_window.is_some_and(|window| window <= 0) {
+            return Err(AppError::ValidationError(
+                "上下文窗口必须大于 0".to_string(),
+            ));
+        }
+        if let Some(max_output_tokens) = input.max_output_tokens {
+            if max_output_tokens <= 0 {
+                return Err(AppError::ValidationError(
+                    "最大输出长度必须大于 0".to_string(),
+                ));
+            }
+            if input
+                .context_window
+                .is_some_and(|window| max_output_tokens >= window)
+            {
+                return Err(AppError::ValidationError(
+                    "最大输出长度必须小于上下文窗口".to_string(),
+                ));
+            }
+        }
+        if input.max_retries.is_some_and(|retries| retries < 0) {
+            return Err(AppError::ValidationError("重试次数不能小于 0".to_string()));
+        }
+        input.parameters.validate_for(provider)?;
+
+        // 备用模型必须存在，且不能是自己
+        for fallback_id in &input.fallback_model_ids {
+            if Some(*fallback_id) == id {
+                return Err(AppError::ValidationError(
+                    "备用模型不能是模型自己".to_string(),
+                ));
+            }
+            if self.get_model(*fallback_id).await?.is_none() {
+                return Err(AppError::ValidationError(format!(
+                    "备用模型不存在: {}",
+                    fallback_id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(row: &mut models::ActiveModel, input: ModelInput) -> AppResult<()> {
+        // 空字符串当作没填
+        let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+        let fallback_model_ids = if input.fallback_model_ids.is_empty() {
+            None
+        } else {
+            let ids = serde_json::to_string(&input.fallback_model_ids)
+                .map_err(|e| AppError::ValidationError(format!("备用模型列表无效: {}", e)))?;
+            Some(ids)
+        };
+
+        // OpenAI 兼容的行直接往 base_url 发请求，只填到 /v1 的要补上 /chat/completions
+        let base_url = match ProviderKind::parse(&input.provider)? {
+            ProviderKind::OpenAI => chat_completions_url(&input.base_url)?,
+            _ => input.base_url.trim().to_string(),
+        };
+
+        row.name = Set(input.name.trim().to_string());
+        row.model_id = Set(input.model_id.trim().to_string());
+        row.base_url = Set(base_url);
+        row.api_key = Set(non_empty(input.api_key));
+        row.icon = Set(non_empty(input.icon));
+        row.description = Set(non_empty(input.description));
+        row.provider = Set(input.provider);
+        row.context_window = Set(input.context_window);
+        row.max_retries = Set(input.max_retries);
+        row.fallback_model_ids = Set(fallback_model_ids);
+        row.max_output_tokens = Set(input.max_output_tokens);
+        row.supports_vision = Set(input.supports_vision);
+        row.supports_tools = Set(input.supports_tools);
+        row.supports_streaming = Set(input.supports_streaming);
+        row.supports_system_role = Set(input.supports_system_role);
+        row.reasoning = Set(input.reasoning);
+        row.parameters = Set(input.parameters.to_json());
+        Ok(())
+    }
+}
+
+// 接口地址必须是完整的 http(s) 地址
+pub fn validate_base_url(base_url: &str) -> AppResult<Url> {
+    let url = Url::parse(base_url.trim())
+        .map_err(|e| AppError::ValidationError(format!("接口地址无效 ({}): {}", e, base_url)))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(AppError::ValidationError(format!(
+            "接口地址必须以 http:// 或 https:// 开头: {}",
+            base_url
+        )));
+    }
+    Ok(url)
+}
+
+// 模型行里存的是 .../chat/completions，也允许只填到 /v1；目录接口是同级的 /models
+fn api_root(base_url: &str) -> AppResult<String> {
+    validate_base_url(base_url)?;
+    let base_url = base_url.trim().trim_end_matches('/');
+    let root = base_url
+        .strip_suffix("/chat/completions")
+        .unwrap_or(base_url);
+    Ok(root.to_string())
+}
+
+fn models_url(base_url: &str) -> AppResult<String> {
+    Ok(format!("{}/models", api_root(base_url)?))
+}
+
+fn chat_completions_url(base_url: &str) -> AppResult<String> {
+    Ok(format!("{}/chat/completions", api_root(base_url)?))
+}
+
+// 从模型 id 猜一个好读的名字，如 "gpt-4o-mini" -> "GPT-4o Mini"，
+// "meta-llama/llama-3.1-8b-instruct" -> "Llama 3.1 8B Instruct"
+pub fn guess_model_name(model_id: &str) -> String {
+    let id = model_id.rsplit('/').next().unwrap_or(model_id);
+    let mut name = String::new();
+    for part in id.split(['-', '_', ':']).filter(|part| !part.is_empty()) {
+        let word = match part.to_ascii_lowercase().as_str() {
+            "gpt" => "GPT".to_string(),
+            // 参数量：8b -> 8B
+            lower if lower.ends_with('b') && lower[..lower.len() - 1].parse::<f32>().is_ok() => {
+                lower.to_ascii_uppercase()
+            }
+            _ => {
+                let mut chars = part.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => continue,
+                }
+            }
+        };
+
+        // "GPT" 后面紧跟版本号，用 "-" 连起来
+        if name.is_empty() {
+            name = word;
+        } else if name.ends_with("GPT") {
+            name.push('-');
+            name.push_str(&word);
+        } else {
+            name.push(' ');
+            name.push_str(&word);
+        }
+    }
+
+    if name.is_empty() {
+        model_id.to_string()
+    } else {
+        name
+    }
+}
+
+// 按模型 id 里的关键字猜图标 (前端按这个名字选图标)
+pub fn guess_model_icon(model_id: &str) -> &'static str {
+    let id = model_id.to_ascii_lowercase();
+    // 厂商关键字更具体，放在前面；"o1" 这类短关键字放最后，免得误伤
+    let icons = [
+        (&["claude"][..], "anthropic"),
+        (&["gemini", "gemma"][..], "google"),
+        (&["llama"][..], "meta"),
+        (&["qwen"][..], "qwen"),
+        (&["deepseek"][..], "deepseek"),
+        (&["mistral", "mixtral", "codestral"][..], "mistral"),
+        (&["glm"][..], "zhipu"),
+        (
+            &["gpt", "davinci", "whisper", "dall-e", "o1", "o3", "o4"][..],
+            "openai",
+        ),
+    ];
+    icons
+        .iter()
+        .find(|(keywords, _)| keywords.iter().any(|keyword| id.contains(keyword)))
+        .map(|(_, icon)| *icon)
+        .unwrap_or("box")
+}
@@ -0,0 +1,451 @@
+use futures::StreamExt;
+use reqwest::Client;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::{
+    entities::{models, prelude::Models},
+    error::{AppError, AppResult},
+    providers::{drain_lines, ollama::api_url, ProviderKind},
+};
+
+// --- 1. /api/tags 返回的本地模型列表 ---
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct OllamaModelInfo {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    pub modified_at: Option<String>,
+    pub details: Option<OllamaModelDetails>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct OllamaModelDetails {
+    pub family: Option<String>,
+    pub parameter_size: Option<String>,
+    pub quantization_level: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<OllamaModelInfo>,
+}
+
+// --- 2. /api/ps 返回的已加载到内存的模型 ---
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct OllamaRunningModel {
+    pub name: String,
+    #[serde(default)]
+    pub size_vram: u64,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PsResponse {
+    #[serde(default)]
+    models: Vec<OllamaRunningModel>,
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+// Ollama 服务状态 (是否在线、版本、哪些模型已经加载)
+#[derive(Serialize, Clone, Debug)]
+pub struct OllamaStatus {
+    pub online: bool,
+    pub version: Option<String>,
+    pub running: Vec<OllamaRunningModel>,
+}
+
+// --- 3. /api/pull 的进度 (NDJSON，每行一个) ---
+#[derive(Deserialize, Debug)]
+struct PullProgress {
+    status: Option<String>,
+    total: Option<u64>,
+    completed: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Clone, Serialize, Debug)]
+struct PullProgressPayload {
+    model: String,
+    status: String,
+    total: Option<u64>,
+    completed: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct OllamaService {
+    db: DatabaseConnection,
+    client: Client,
+}
+
+impl OllamaService {
+    pub fn new(db: &DatabaseConnection) -> Self {
+        Self {
+            db: db.clone(),
+            client: Client::new(),
+        }
+    }
+
+    // 1. 获取本机已安装的模型
+    pub async fn list_local_models(&self, base_url: &str) -> AppResult<Vec<OllamaModelInfo>> {
+        let response: TagsResponse = self
+            .client
+            .get(api_url(base_url, "/api/tags"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.models)
+    }
+
+    // 2. 把本机已安装、但 models 表里还没有的模型自动加进去，返回新增的行
+    pub async fn sync_models(&self, base_url: &str) -> AppResult<Vec<models::Model>> {
+        let local_models = self.list_local_models(base_url).await?;
+        // 同一个服务的地址可能写成带 / 或者带 /api/chat 的形式，统一成服务根地址再比较
+        let root = api_url(base_url, "");
+        let existing: Vec<String> = Models::find()
+            .filter(models::Column::Provider.eq(ProviderKind::Ollama.as_str()))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .filter(|row| api_url(&row.base_url, "") == root)
+            .map(|row| row.model_id)
+            .collect();
+
+        let mut inserted = Vec::new();
+        for info in local_models {
+            if existing.contains(&info.name) {
+                continue;
+            }
+
+            // 备注里记下参数量和量化方式，如 "8B Q4_K_M"
+            let description = info.details.as_ref().map(|details| {
+                [&details.parameter_size, &details.quantization_level]
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+
+            let row = models::ActiveModel {
+                name: Set(info.name.clone()),
+                model_id: Set(info.name),
+                base_url: Set(root.clone()),
+                api_key: Set(None),
+                icon: Set(Some("ollama".to_string())),
+                description: Set(description),
+                provider: Set(ProviderKind::Ollama.as_str().to_string()),
+                ..Default::default()
+            };
+            inserted.push(row.insert(&self.db).await?);
+        }
+
+        Ok(inserted)
+    }
+
+    // 3. 服务状态：离线时不报错，只返回 online = false
+    pub async fn status(&self, base_url: &str) -> AppResult<OllamaStatus> {
+        // 连不上、返回非 2xx 或者不是 Ollama 的响应，都当作不在线
+        let version = match self.fetch_version(base_url).await {
+            Ok(version) => version,
+            Err(_) => {
+                return Ok(OllamaStatus {
+                    online: false,
+                    version: None,
+                    running: Vec::new(),
+                })
+            }
+        };
+
+        let running: PsResponse = self
+            .client
+            .get(api_url(base_url, "/api/ps"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(OllamaStatus {
+            online: true,
+            version: Some(version),
+            running: running.models,
+        })
+    }
+
+    async fn fetch_version(&self, base_url: &str) -> AppResult<String> {
+        let response: VersionResponse = self
+            .client
+            .get(api_url(base_url, "/api/version"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.version)
+    }
+
+    // 4. 下载模型，进度通过 "ollama-pull-progress" 事件推给前端，完成后同步到 models 表
+    pub async fn pull_model(
+        &self,
+        app: &AppHandle,
+        base_url: &str,
+        model: &str,
+    ) -> AppResult<Vec<models::Model>> {
+        self.pull(base_url, model, |payload| {
+            app.emit("ollama-pull-progress", payload)?;
+            Ok(())
+        })
+        .await
+    }
+
+    // 下载模型，每一行进度交给 on_progress
+    async fn pull(
+        &self,
+        base_url: &str,
+        model: &str,
+        mut on_progress: impl FnMut(&PullProgressPayload) -> AppResult<()>,
+    ) -> AppResult<Vec<models::Model>> {
+        let response = self
+            .client
+            .post(api_url(base_url, "/api/pull"))
+            .json(&serde_json::json!({ "model": model, "stream": true }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let mut stream = response.bytes_stream();
+        let mut buffer = Vec::new();
+        while let Some(bytes) = stream.next().await {
+            buffer.extend_from_slice(&bytes?);
+            for line in drain_lines(&mut buffer) {
+                let Ok(progress) = serde_json::from_str::<PullProgress>(&line) else {
+                    continue;
+                };
+                if let Some(error) = progress.error {
+                    return Err(AppError::AiError(error));
+                }
+
+                let payload = PullProgressPayload {
+                    model: model.to_string(),
+                    status: progress.status.unwrap_or_default(),
+                    total: progress.total,
+                    completed: progress.completed,
+                };
+                on_progress(&payload)?;
+            }
+        }
+
+        self.sync_models(base_url).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const TAGS: &str = r#"{"models":[
+        {"name":"llama3.1:8b","size":4920753328,"details":{"parameter_size":"8B","quantization_level":"Q4_K_M"}},
+        {"name":"qwen2.5:7b","size":4683087332}
+    ]}"#;
+
+    // 本地的假 Ollama 服务：按路径返回固定的状态码和响应体，没配置的路径返回 404
+    async fn stub_server(routes: Vec<(&'static str, u16, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let routes = routes.clone();
+                tokio::spawn(async move {
+                    let path = read_request(&mut socket).await;
+                    let (status, body) = routes
+                        .iter()
+                        .find(|(route, ..)| *route == path)
+                        .map(|(_, status, body)| (*status, *body))
+                        .unwrap_or((404, "not found"));
+                    let response = format!(
+                        "HTTP/1.1 {} STUB\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    // 读完请求头和请求体 (按 Content-Length)，返回请求路径
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut chunk = [0u8; 1024];
+        let header_end = loop {
+            if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let n = socket.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                return String::new();
+            }
+            request.extend_from_slice(&chunk[..n]);
+        };
+
+        let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+        let content_length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        while request.len() < header_end + content_length {
+            let n = socket.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&chunk[..n]);
+        }
+
+        head.split_whitespace()
+            .nth(1)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    async fn service() -> OllamaService {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        OllamaService::new(&db)
+    }
+
+    #[tokio::test]
+    async fn lists_local_models() {
+        let base_url = stub_server(vec![("/api/tags", 200, TAGS)]).await;
+        let models = service().await.list_local_models(&base_url).await.unwrap();
+
+        let names: Vec<_> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["llama3.1:8b", "qwen2.5:7b"]);
+        let details = models[0].details.as_ref().unwrap();
+        assert_eq!(details.parameter_size.as_deref(), Some("8B"));
+    }
+
+    #[tokio::test]
+    async fn sync_skips_models_already_added_under_another_url_form() {
+        let base_url = stub_server(vec![("/api/tags", 200, TAGS)]).await;
+        let service = service().await;
+
+        let inserted = service.sync_models(&base_url).await.unwrap();
+        assert_eq!(inserted.len(), 2);
+        assert_eq!(inserted[0].base_url, base_url);
+        assert_eq!(inserted[0].description.as_deref(), Some("8B Q4_K_M"));
+
+        for same_server in [format!("{}/", base_url), format!("{}/api/chat", base_url)] {
+            let inserted = service.sync_models(&same_server).await.unwrap();
+            assert!(
+                inserted.is_empty(),
+                "re-inserted models for {}",
+                same_server
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_online_status() {
+        let base_url = stub_server(vec![
+            ("/api/version", 200, r#"{"version":"0.5.7"}"#),
+            (
+                "/api/ps",
+                200,
+                r#"{"models":[{"name":"llama3.1:8b","size_vram":5000000000}]}"#,
+            ),
+        ])
+        .await;
+        let status = service().await.status(&base_url).await.unwrap();
+
+        assert!(status.online);
+        assert_eq!(status.version.as_deref(), Some("0.5.7"));
+        assert_eq!(status.running.len(), 1);
+        assert_eq!(status.running[0].name, "llama3.1:8b");
+    }
+
+    #[tokio::test]
+    async fn reports_offline_on_error_or_non_ollama_replies() {
+        let service = service().await;
+
+        let not_found = stub_server(Vec::new()).await;
+        assert!(!service.status(&not_found).await.unwrap().online);
+
+        let not_json = stub_server(vec![("/api/version", 200, "<html>hello</html>")]).await;
+        assert!(!service.status(&not_json).await.unwrap().online);
+    }
+
+    #[tokio::test]
+    async fn pulls_model_and_reports_progress() {
+        let base_url = stub_server(vec![
+            (
+                "/api/pull",
+                200,
+                "{\"status\":\"pulling manifest\"}\n\
+                 {\"status\":\"downloading\",\"total\":100,\"completed\":40}\n\
+                 {\"status\":\"success\"}\n",
+            ),
+            ("/api/tags", 200, TAGS),
+        ])
+        .await;
+        let service = service().await;
+
+        let mut progress = Vec::new();
+        let inserted = service
+            .pull(&base_url, "llama3.1:8b", |payload| {
+                progress.push((payload.status.clone(), payload.completed));
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            progress,
+            [
+                ("pulling manifest".to_string(), None),
+                ("downloading".to_string(), Some(40)),
+                ("success".to_string(), None),
+            ]
+        );
+        assert_eq!(inserted.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn pull_error_line_fails_the_pull() {
+        let base_url = stub_server(vec![(
+            "/api/pull",
+            200,
+            "{\"status\":\"pulling manifest\"}\n{\"error\":\"pull model manifest: file does not exist\"}\n",
+        )])
+        .await;
+
+        let result = service()
+            .await
+            .pull(&base_url, "no-such-model", |_| Ok(()))
+            .await;
+        assert!(
+            matches!(&result, Err(AppError::AiError(message)) if message.contains("does not exist")),
+            "unexpected result: {:?}",
+            result
+        );
+    }
+}
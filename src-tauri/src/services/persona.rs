@@ -0,0 +1,126 @@
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+use serde::Deserialize;
+
+use crate::{
+    entities::{
+        conversations, personas,
+        prelude::{Conversations, Models, Personas},
+    },
+    error::{AppError, AppResult},
+    providers::{GenerationParams, ProviderKind},
+};
+
+// 前端提交的人设表单
+#[derive(Deserialize, Debug)]
+pub struct PersonaInput {
+    pub name: String,
+    pub system_prompt: String,
+    pub model_id: Option<i64>, // 为空时使用会话或全局的默认模型
+    #[serde(default)]
+    pub parameters: GenerationParams,
+    pub avatar: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct PersonaService {
+    db: DatabaseConnection,
+}
+
+impl PersonaService {
+    pub fn new(db: &DatabaseConnection) -> Self {
+        Self { db: db.clone() }
+    }
+
+    // 1. 人设列表
+    pub async fn list_personas(&self) -> AppResult<Vec<personas::Model>> {
+        let personas = Personas::find()
+            .order_by_asc(personas::Column::Id)
+            .all(&self.db)
+            .await?;
+        Ok(personas)
+    }
+
+    pub async fn get_persona(&self, id: i64) -> AppResult<Option<personas::Model>> {
+        let persona = Personas::find_by_id(id).one(&self.db).await?;
+        Ok(persona)
+    }
+
+    // 会话使用的人设，没有设置时为 None
+    pub async fn get_session_persona(&self, session_id: i64) -> AppResult<Option<personas::Model>> {
+        let conversation = Conversations::find_by_id(session_id).one(&self.db).await?;
+        match conversation.and_then(|conversation| conversation.persona_id) {
+            Some(persona_id) => self.get_persona(persona_id).await,
+            None => Ok(None),
+        }
+    }
+
+    // 2. 新建人设
+    pub async fn create_persona(&self, input: PersonaInput) -> AppResult<personas::Model> {
+        self.validate(&input).await?;
+
+        let mut row = personas::ActiveModel::default();
+        Self::apply(&mut row, input);
+        let persona = row.insert(&self.db).await?;
+        Ok(persona)
+    }
+
+    // 3. 修改人设
+    pub async fn update_persona(&self, id: i64, input: PersonaInput) -> AppResult<personas::Model> {
+        let existing = self
+            .get_persona(id)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("人设不存在: {}", id)))?;
+        self.validate(&input).await?;
+
+        let mut row: personas::ActiveModel = existing.into();
+        Self::apply(&mut row, input);
+        let persona = row.update(&self.db).await?;
+        Ok(persona)
+    }
+
+    // 4. 删除人设：用到它的会话改回不使用人设
+    pub async fn delete_persona(&self, id: i64) -> AppResult<()> {
+        Conversations::update_many()
+            .col_expr(
+                conversations::Column::PersonaId,
+                Expr::value(Option::<i64>::None),
+            )
+            .filter(conversations::Column::PersonaId.eq(id))
+            .exec(&self.db)
+            .await?;
+
+        Personas::delete_by_id(id).exec(&self.db).await?;
+        Ok(())
+    }
+
+    async fn validate(&self, input: &PersonaInput) -> AppResult<()> {
+        if input.name.trim().is_empty() {
+            return Err(AppError::ValidationError("人设名称不能为空".to_string()));
+        }
+        if input.system_prompt.trim().is_empty() {
+            return Err(AppError::ValidationError("系统提示词不能为空".to_string()));
+        }
+        let Some(model_id) = input.model_id else {
+            return input.parameters.validate();
+        };
+        let model = Models::find_by_id(model_id)
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("模型不存在: {}", model_id)))?;
+        // 指定了模型时按这个模型的接口限制校验参数
+        input
+            .parameters
+            .validate_for(ProviderKind::parse(&model.provider)?)
+    }
+
+    fn apply(row: &mut personas::ActiveModel, input: PersonaInput) {
+        row.name = Set(input.name.trim().to_string());
+        row.system_prompt = Set(input.system_prompt.trim().to_string());
+        row.model_id = Set(input.model_id);
+        row.parameters = Set(input.parameters.to_json());
+        row.avatar = Set(input.avatar.filter(|avatar| !avatar.trim().is_empty()));
+    }
+}
@@ -1,24 +1,52 @@
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, DatabaseConnection, EntityTrait, QueryOrder};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
 
 use crate::{
-    entities::{conversations, prelude::Conversations},
-    error::AppResult,
+    entities::{
+        conversations, messages,
+        prelude::{Conversations, Models, Personas},
+    },
+    error::{AppError, AppResult},
+    providers::GenerationParams,
+    services::chat::ChatService,
 };
 
+// 没有给标题时的占位标题
+pub const PLACEHOLDER_TITLE: &str = "新对话";
+
 #[derive(Clone)]
 pub struct SessionService {
     db: DatabaseConnection,
+    chat_service: ChatService, // 复制会话时按分支取消息
 }
 
 impl SessionService {
-    pub fn new(db: &DatabaseConnection) -> Self {
-        Self { db: db.clone() }
+    pub fn new(db: &DatabaseConnection, chat_service: ChatService) -> Self {
+        Self {
+            db: db.clone(),
+            chat_service,
+        }
     }
 
-    // 1. 创建新会话
-    pub async fn create_session(&self, title: &str) -> AppResult<conversations::Model> {
+    // 1. 创建新会话 (model_id 为空时跟随人设或默认模型，persona_id 为空时不使用人设)
+    // 没有给标题时先用占位标题，第一轮问答之后自动生成
+    pub async fn create_session(
+        &self,
+        title: Option<&str>,
+        model_id: Option<i64>,
+        persona_id: Option<i64>,
+    ) -> AppResult<conversations::Model> {
+        self.ensure_model_exists(model_id).await?;
+        self.ensure_persona_exists(persona_id).await?;
+        let title = title.map(str::trim).filter(|title| !title.is_empty());
         let new_session = conversations::ActiveModel {
-            title: Set(title.to_string()),
+            title: Set(title.unwrap_or(PLACEHOLDER_TITLE).to_string()),
+            title_is_auto: Set(title.is_none()),
+            // 列上的默认值是 1，必须显式写入，否则会话会固定到 1 号模型
+            model_id: Set(model_id),
+            persona_id: Set(persona_id),
             ..Default::default()
         };
         let session = new_session.insert(&self.db).await?;
@@ -34,4 +62,209 @@ impl SessionService {
 
         Ok(sessions)
     }
+
+    // 切换会话使用的模型 (传空则改回跟随默认模型)，之后的生成都用新模型
+    pub async fn set_session_model(
+        &self,
+        session_id: i64,
+        model_id: Option<i64>,
+    ) -> AppResult<conversations::Model> {
+        self.ensure_model_exists(model_id).await?;
+        let session = Conversations::find_by_id(session_id)
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("会话不存在: {}", session_id)))?;
+
+        let mut active: conversations::ActiveModel = session.into();
+        active.model_id = Set(model_id);
+        let session = active.update(&self.db).await?;
+        Ok(session)
+    }
+
+    // 设置会话的生成参数覆盖值 (全部为空则改回完全使用模型的参数)
+    pub async fn set_session_parameters(
+        &self,
+        session_id: i64,
+        overrides: &GenerationParams,
+    ) -> AppResult<conversations::Model> {
+        overrides.validate()?;
+        let session = Conversations::find_by_id(session_id)
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("会话不存在: {}", session_id)))?;
+
+        let mut active: conversations::ActiveModel = session.into();
+        active.parameters = Set(overrides.to_json());
+        let session = active.update(&self.db).await?;
+        Ok(session)
+    }
+
+    // 修改会话标题 (用户改名或者手动生成的标题，之后不会再被自动标题覆盖)
+    pub async fn set_session_title(
+        &self,
+        session_id: i64,
+        title: &str,
+    ) -> AppResult<conversations::Model> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err(AppError::ValidationError("会话标题不能为空".to_string()));
+        }
+        let session = Conversations::find_by_id(session_id)
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("会话不存在: {}", session_id)))?;
+
+        let mut active: conversations::ActiveModel = session.into();
+        active.title = Set(title.to_string());
+        active.title_is_auto = Set(false);
+        let session = active.update(&self.db).await?;
+        Ok(session)
+    }
+
+    // 写入自动生成的标题：只有标题还是占位标题时才写 (生成期间用户可能已经改了名)，
+    // 没有写入时返回 None
+    pub async fn set_auto_title(
+        &self,
+        session_id: i64,
+        title: &str,
+    ) -> AppResult<Option<conversations::Model>> {
+        let result = Conversations::update_many()
+            .col_expr(conversations::Column::Title, Expr::value(title))
+            .col_expr(conversations::Column::TitleIsAuto, Expr::value(false))
+            .filter(conversations::Column::Id.eq(session_id))
+            .filter(conversations::Column::TitleIsAuto.eq(true))
+            .exec(&self.db)
+            .await?;
+        if result.rows_affected == 0 {
+            return Ok(None);
+        }
+        let session = Conversations::find_by_id(session_id).one(&self.db).await?;
+        Ok(session)
+    }
+
+    // 删除会话，消息和摘要通过外键级联删除
+    pub async fn delete_session(&self, session_id: i64) -> AppResult<()> {
+        let result = Conversations::delete_by_id(session_id)
+            .exec(&self.db)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(AppError::ValidationError(format!(
+                "会话不存在: {}",
+                session_id
+            )));
+        }
+        Ok(())
+    }
+
+    // 切换会话使用的人设 (传空则不使用人设)，之后的生成都带上新人设的系统提示词
+    pub async fn set_session_persona(
+        &self,
+        session_id: i64,
+        persona_id: Option<i64>,
+    ) -> AppResult<conversations::Model> {
+        self.ensure_persona_exists(persona_id).await?;
+        let session = Conversations::find_by_id(session_id)
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("会话不存在: {}", session_id)))?;
+
+        let mut active: conversations::ActiveModel = session.into();
+        active.persona_id = Set(persona_id);
+        let session = active.update(&self.db).await?;
+        Ok(session)
+    }
+
+    // 从某条消息处分出一个新会话：复制从开头到这条消息的这一段对话 (只复制这条分支)，
+    // 模型、人设和生成参数沿用原会话，原会话不受影响
+    pub async fn fork_session(
+        &self,
+        session_id: i64,
+        message_id: i64,
+        title: Option<String>,
+    ) -> AppResult<conversations::Model> {
+        let source = Conversations::find_by_id(session_id)
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| AppError::ValidationError(format!("会话不存在: {}", session_id)))?;
+        let path = self.chat_service.get_path(session_id, message_id).await?;
+        if path.last().map(|msg| msg.id) != Some(message_id) {
+            return Err(AppError::ValidationError(format!(
+                "消息不存在: {}",
+                message_id
+            )));
+        }
+
+        let title = title
+            .map(|title| title.trim().to_string())
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| format!("{} (分支)", source.title));
+
+        let txn = self.db.begin().await?;
+        let fork = conversations::ActiveModel {
+            title: Set(title),
+            title_is_auto: Set(false),
+            model_id: Set(source.model_id),
+            persona_id: Set(source.persona_id),
+            parameters: Set(source.parameters),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        // 按顺序复制，每条消息挂到上一条复制出来的消息下
+        let mut parent_id = None;
+        for msg in path {
+            let copied = messages::ActiveModel {
+                conversation_id: Set(fork.id),
+                role: Set(msg.role),
+                content: Set(msg.content),
+                created_at: Set(msg.created_at),
+                status: Set(msg.status),
+                error: Set(msg.error),
+                model_id: Set(msg.model_id),
+                model_name: Set(msg.model_name),
+                parent_id: Set(parent_id),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+            parent_id = Some(copied.id);
+        }
+
+        let mut fork: conversations::ActiveModel = fork.into();
+        fork.active_leaf_id = Set(parent_id);
+        let fork = fork.update(&txn).await?;
+        txn.commit().await?;
+        Ok(fork)
+    }
+
+    async fn ensure_model_exists(&self, model_id: Option<i64>) -> AppResult<()> {
+        let Some(model_id) = model_id else {
+            return Ok(());
+        };
+        if Models::find_by_id(model_id).one(&self.db).await?.is_none() {
+            return Err(AppError::ValidationError(format!(
+                "模型不存在: {}",
+                model_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn ensure_persona_exists(&self, persona_id: Option<i64>) -> AppResult<()> {
+        let Some(persona_id) = persona_id else {
+            return Ok(());
+        };
+        if Personas::find_by_id(persona_id)
+            .one(&self.db)
+            .await?
+            .is_none()
+        {
+            return Err(AppError::ValidationError(format!(
+                "人设不存在: {}",
+                persona_id
+            )));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,53 @@
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+use crate::{
+    entities::{conversation_summaries, prelude::ConversationSummaries},
+    error::AppResult,
+};
+
+#[derive(Clone)]
+pub struct SummaryService {
+    db: DatabaseConnection,
+}
+
+impl SummaryService {
+    pub fn new(db: &DatabaseConnection) -> Self {
+        Self { db: db.clone() }
+    }
+
+    // 获取会话最新的一份摘要 (覆盖范围最大的那份)
+    // 摘要只对生成它时的那条分支有效，所以只看覆盖到 path 上某条消息为止的摘要
+    pub async fn get_latest(
+        &self,
+        session_id: i64,
+        path: &[i64],
+    ) -> AppResult<Option<conversation_summaries::Model>> {
+        let summary = ConversationSummaries::find()
+            .filter(conversation_summaries::Column::ConversationId.eq(session_id))
+            .filter(conversation_summaries::Column::LastMessageId.is_in(path.iter().copied()))
+            .order_by_desc(conversation_summaries::Column::LastMessageId)
+            .one(&self.db)
+            .await?;
+        Ok(summary)
+    }
+
+    // 保存一份新摘要，last_message_id 是这份摘要覆盖到的最后一条消息
+    pub async fn save_summary(
+        &self,
+        session_id: i64,
+        last_message_id: i64,
+        content: &str,
+    ) -> AppResult<conversation_summaries::Model> {
+        let summary = conversation_summaries::ActiveModel {
+            conversation_id: Set(session_id),
+            last_message_id: Set(last_message_id),
+            content: Set(content.to_string()),
+            ..Default::default()
+        };
+        let saved = summary.insert(&self.db).await?;
+        Ok(saved)
+    }
+}
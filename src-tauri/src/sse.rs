@@ -0,0 +1,211 @@
+// Server-Sent Events 解码器
+//
+// 网络包的边界和事件的边界没有任何关系：一个包里可能有好几个事件，
+// 一个 JSON 甚至一个多字节的 UTF-8 字符也可能被拆到两个包里。
+// 所以这里先按字节缓冲，凑齐一整行再解码，遇到空行才算一个完整的事件。
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: Option<String>, // "event:" 字段，没有时按规范视为 "message"
+    pub data: String,          // 多个 "data:" 行用换行拼接
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,       // 还没凑成一整行的字节
+    event: Option<String>, // 正在组装的事件
+    data: Option<String>,
+    id: Option<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 喂入一个网络包，返回其中所有已经完整的事件
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            line.pop(); // 去掉 \n
+            if line.last() == Some(&b'\r') {
+                line.pop(); // 兼容 \r\n 换行
+            }
+
+            let line = String::from_utf8_lossy(&line);
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    // 流结束时调用：最后一个事件后面可能没有空行，也要吐出来
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line: Vec<u8> = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches('\r');
+            if let Some(event) = self.process_line(line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        // 空行：事件结束
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // 冒号开头是注释 (常用作保活)
+        if line.starts_with(':') {
+            return None;
+        }
+
+        // "field: value"，冒号后面的第一个空格不算值；没有冒号时整行都是字段名
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            // 按规范，包含 NUL 的 id 直接忽略
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            // retry 以及未知字段都忽略
+            _ => {}
+        }
+        None
+    }
+
+    // 没有 data 的事件按规范不分发
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let data = self.data.take()?;
+        Some(SseEvent {
+            event,
+            data,
+            id: self.id.clone(), // id 会一直沿用到下一次被设置
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_chunks(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events: Vec<SseEvent> = chunks
+            .iter()
+            .flat_map(|chunk| decoder.push(chunk))
+            .collect();
+        events.extend(decoder.finish());
+        events
+    }
+
+    fn data_event(data: &str) -> SseEvent {
+        SseEvent {
+            data: data.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decodes_events_in_a_single_chunk() {
+        let events = decode_chunks(&[b"data: {\"a\":1}\n\ndata: {\"a\":2}\n\n"]);
+        assert_eq!(
+            events,
+            vec![data_event("{\"a\":1}"), data_event("{\"a\":2}")]
+        );
+    }
+
+    #[test]
+    fn joins_json_split_across_chunks() {
+        let events = decode_chunks(&[b"data: {\"choices\":[{\"del", b"ta\":{}}]}\n", b"\n"]);
+        assert_eq!(events, vec![data_event("{\"choices\":[{\"delta\":{}}]}")]);
+    }
+
+    #[test]
+    fn keeps_multibyte_characters_split_across_chunks() {
+        let payload = "data: 你好\n\n".as_bytes();
+        // "你" 占 3 个字节，从它中间切开
+        let (first, second) = payload.split_at(7);
+        let events = decode_chunks(&[first, second]);
+        assert_eq!(events, vec![data_event("你好")]);
+    }
+
+    #[test]
+    fn handles_crlf_line_endings() {
+        let events = decode_chunks(&[b"data: hello\r\n\r", b"\ndata: world\r\n\r\n"]);
+        assert_eq!(events, vec![data_event("hello"), data_event("world")]);
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let events = decode_chunks(&[b"data: line one\ndata: line two\n\n"]);
+        assert_eq!(events, vec![data_event("line one\nline two")]);
+    }
+
+    #[test]
+    fn parses_event_and_id_fields() {
+        let events = decode_chunks(&[
+            b"event: content_block_delta\nid: 7\ndata: {}\n\n",
+            b"data: next\n\n",
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("content_block_delta".to_string()),
+                    data: "{}".to_string(),
+                    id: Some("7".to_string()),
+                },
+                // event 只对一个事件生效，id 会沿用
+                SseEvent {
+                    event: None,
+                    data: "next".to_string(),
+                    id: Some("7".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_comments_and_events_without_data() {
+        let events = decode_chunks(&[b": keep-alive\n\nevent: ping\n\ndata: ok\n\n"]);
+        assert_eq!(events, vec![data_event("ok")]);
+    }
+
+    #[test]
+    fn accepts_values_without_leading_space() {
+        let events = decode_chunks(&[b"data:[DONE]\n\n"]);
+        assert_eq!(events, vec![data_event("[DONE]")]);
+    }
+
+    #[test]
+    fn flushes_last_event_without_trailing_blank_line() {
+        let events = decode_chunks(&[b"data: first\n\ndata: last"]);
+        assert_eq!(events, vec![data_event("first"), data_event("last")]);
+    }
+
+    #[test]
+    fn waits_for_blank_line_before_dispatching() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: partial\n").is_empty());
+        assert_eq!(decoder.push(b"\n"), vec![data_event("partial")]);
+    }
+}
@@ -1,5 +1,115 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tauri::async_runtime::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
 use crate::services::AppServices;
 
 pub struct AppState {
     pub services: AppServices,
+    pub generations: GenerationRegistry,
+}
+
+// 一次生成的身份：id 随事件发给前端，cancel 用来中途停止
+#[derive(Clone, Debug)]
+pub struct GenerationTicket {
+    pub id: u64,
+    pub cancel: CancellationToken,
+}
+
+struct RunningGeneration {
+    id: u64,
+    token: CancellationToken,
+    // 后台生成任务，清空/删除会话前要等它结束
+    task: Option<JoinHandle<()>>,
+}
+
+// 正在进行的 AI 生成 (按会话 id 记录)，用来中途停止
+// 被停止或被新生成替换的任务在真正结束 (finish) 之前也留在这里，清空/删除会话时要等它们
+#[derive(Clone, Default)]
+pub struct GenerationRegistry {
+    running: Arc<Mutex<HashMap<i64, Vec<RunningGeneration>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl GenerationRegistry {
+    // 在后台启动一次新的生成并登记；同一个会话里还在跑的旧生成会被取消
+    // 任务在持锁期间启动并登记，不会出现任务已经在跑、清空会话却等不到它的情况
+    pub fn spawn<F, Fut>(&self, session_id: i64, run: F)
+    where
+        F: FnOnce(GenerationTicket) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let token = CancellationToken::new();
+
+        let mut running = self.running.lock().unwrap();
+        let generations = running.entry(session_id).or_default();
+        for previous in generations.iter() {
+            previous.token.cancel();
+        }
+
+        let future = run(GenerationTicket {
+            id,
+            cancel: token.clone(),
+        });
+        let registry = self.clone();
+        let task = tauri::async_runtime::spawn(async move {
+            future.await;
+            registry.finish(session_id, id);
+        });
+        generations.push(RunningGeneration {
+            id,
+            token,
+            task: Some(task),
+        });
+    }
+
+    // 生成结束后注销 (只注销自己，同一会话里的其他生成不动)
+    fn finish(&self, session_id: i64, generation_id: u64) {
+        let mut running = self.running.lock().unwrap();
+        if let Some(generations) = running.get_mut(&session_id) {
+            generations.retain(|generation| generation.id != generation_id);
+            if generations.is_empty() {
+                running.remove(&session_id);
+            }
+        }
+    }
+
+    // 停止会话里正在进行的生成，没有正在进行的生成时返回 false
+    // (任务要等保存完回复才注销，这里只取消)
+    pub fn cancel(&self, session_id: i64) -> bool {
+        let running = self.running.lock().unwrap();
+        let mut stopped = false;
+        for generation in running.get(&session_id).into_iter().flatten() {
+            stopped |= !generation.token.is_cancelled();
+            generation.token.cancel();
+        }
+        stopped
+    }
+
+    // 停止会话里所有还没结束的生成 (包括已经停止、被替换的)，并等它们真正结束
+    // (回复保存完或者放弃保存)，清空或删除会话之前调用，避免生成晚一步把回复写回去
+    pub async fn cancel_and_wait(&self, session_id: i64) {
+        let tasks: Vec<JoinHandle<()>> = {
+            let mut running = self.running.lock().unwrap();
+            running
+                .get_mut(&session_id)
+                .into_iter()
+                .flatten()
+                .filter_map(|generation| {
+                    generation.token.cancel();
+                    generation.task.take()
+                })
+                .collect()
+        };
+        for task in tasks {
+            if let Err(e) = task.await {
+                eprintln!("等待生成结束失败: {}", e);
+            }
+        }
+    }
 }
@@ -0,0 +1,68 @@
+use serde::Serialize;
+
+// 每条消息除了内容之外的固定开销 (角色标记、分隔符等)
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+// 模型开始回复前的固定开销
+pub const REPLY_PRIMING_TOKENS: usize = 3;
+// 模型没有配置 context_window 时使用的默认值
+pub const DEFAULT_CONTEXT_WINDOW: usize = 4096;
+// 给模型回复预留的 token 数
+pub const DEFAULT_RESERVED_OUTPUT_TOKENS: usize = 1024;
+
+// 可插拔的分词器：以后可以换成 tiktoken 之类的精确实现
+pub trait Tokenizer: Send + Sync {
+    fn count_tokens(&self, text: &str) -> usize;
+
+    // 一条消息占用的 token 数 (内容 + 角色 + 固定开销)
+    fn count_message_tokens(&self, role: &str, content: &str) -> usize {
+        self.count_tokens(role) + self.count_tokens(content) + MESSAGE_OVERHEAD_TOKENS
+    }
+}
+
+// 启发式估算：英文大约 4 个字符一个 token，中日韩等非 ASCII 字符按一个字一个 token 算
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeuristicTokenizer;
+
+impl Tokenizer for HeuristicTokenizer {
+    fn count_tokens(&self, text: &str) -> usize {
+        let (ascii, non_ascii) = text.chars().fold((0usize, 0usize), |(a, n), c| {
+            if c.is_ascii() {
+                (a + 1, n)
+            } else {
+                (a, n + 1)
+            }
+        });
+        ascii.div_ceil(4) + non_ascii
+    }
+}
+
+// 一次请求的上下文预算，前端可以据此显示 "已用 N / 共 M tokens"
+#[derive(Clone, Serialize, Debug)]
+pub struct ContextBudget {
+    pub used_tokens: usize,
+    pub limit_tokens: usize,
+    pub context_window: usize,
+    pub reserved_output_tokens: usize,
+    pub dropped_messages: usize,
+}
+
+impl ContextBudget {
+    pub fn new(context_window: usize) -> Self {
+        Self::with_reserved_output(context_window, None)
+    }
+
+    // 预留给回复的部分 (默认 DEFAULT_RESERVED_OUTPUT_TOKENS) 不超过上下文窗口的四分之一，
+    // 避免小窗口模型没有空间放历史
+    pub fn with_reserved_output(context_window: usize, reserved_output: Option<usize>) -> Self {
+        let reserved_output_tokens = reserved_output
+            .unwrap_or(DEFAULT_RESERVED_OUTPUT_TOKENS)
+            .min(context_window / 4);
+        Self {
+            used_tokens: 0,
+            limit_tokens: context_window - reserved_output_tokens,
+            context_window,
+            reserved_output_tokens,
+            dropped_messages: 0,
+        }
+    }
+}
@@ -14,14 +14,40 @@ interface ChatAreaProps {
 }
 
 interface StreamPayload {
+  session_id: number;
+  generation_id: number;
+  seq: number;
   chunk: string;
   done: boolean;
+  message_id: number | null;
+}
+
+interface ErrorPayload {
+  session_id: number;
+  generation_id: number;
+  message_id: number | null;
+  code: string;
+  message: string;
+  details: Record<string, unknown> | null;
+  retryable: boolean;
+}
+
+interface RetryingPayload {
+  session_id: number;
+  generation_id: number;
+  retry: number;
+  max_retries: number;
+  delay_ms: number;
+  code: string;
+  message: string;
 }
 
 export function ChatArea({ sessionId }: ChatAreaProps) {
   const [messages, setMessages] = useState<Message[]>([]);
   const [input, setInput] = useState("");
   const [isLoading, setIsLoading] = useState(false);
+  // 请求失败正在重试时显示 "重试中 (2/3)"，收到新的输出或者生成结束就清掉
+  const [retrying, setRetrying] = useState<RetryingPayload | null>(null);
   const scrollRef = useRef<HTMLDivElement>(null);
 
   // 1. 监听 sessionId 变化，加载对应的历史记录
@@ -54,11 +80,14 @@ export function ChatArea({ sessionId }: ChatAreaProps) {
     scrollRef.current?.scrollIntoView({ behavior: "smooth" });
   }, [messages]);
 
-  // 3. 监听 AI 回复 (逻辑保持不变，因为是全局事件)
-  // 如果你想做得更细，可以在 event payload 里带上 sessionId，前端判断是否匹配当前 ID
+  // 3. 监听 AI 回复
+  // 事件是全局广播的，payload 里带着 session_id，只处理当前会话的输出
   useEffect(() => {
     const unlistenPromise = listen<StreamPayload>("ai-response", (event) => {
-      const { chunk } = event.payload;
+      const { session_id, chunk } = event.payload;
+      if (session_id !== sessionId) return;
+      setRetrying(null);
+      if (!chunk) return;
       setMessages((prev) => {
         const lastMsg = prev[prev.length - 1];
         if (lastMsg && lastMsg.role === "assistant") {
@@ -77,7 +106,47 @@ export function ChatArea({ sessionId }: ChatAreaProps) {
     return () => {
       unlistenPromise.then((f) => f());
     };
-  }, []);
+  }, [sessionId]);
+
+  // 4. 生成失败：把错误挂到这次的回复上 (后端已经把失败的回复存进历史)
+  useEffect(() => {
+    const unlistenPromise = listen<ErrorPayload>("ai-error", (event) => {
+      const { session_id, message_id, message } = event.payload;
+      if (session_id !== sessionId) return;
+      setMessages((prev) => {
+        const failed: Message = {
+          id: message_id ?? Date.now(),
+          role: "assistant",
+          content: "",
+          status: "error",
+          error: message,
+        };
+        const lastMsg = prev[prev.length - 1];
+        if (lastMsg && lastMsg.role === "assistant") {
+          return [
+            ...prev.slice(0, -1),
+            { ...lastMsg, ...failed, content: lastMsg.content },
+          ];
+        }
+        return [...prev, failed];
+      });
+    });
+    return () => {
+      unlistenPromise.then((f) => f());
+    };
+  }, [sessionId]);
+
+  // 5. 请求失败，后端正在退避重试
+  useEffect(() => {
+    setRetrying(null);
+    const unlistenPromise = listen<RetryingPayload>("ai-retrying", (event) => {
+      if (event.payload.session_id !== sessionId) return;
+      setRetrying(event.payload);
+    });
+    return () => {
+      unlistenPromise.then((f) => f());
+    };
+  }, [sessionId]);
 
   const handleSend = async () => {
     if (!input.trim() || isLoading || sessionId === null) return; // 如果没有 ID，不能发送
@@ -140,6 +209,11 @@ export function ChatArea({ sessionId }: ChatAreaProps) {
                 <MessageBubble key={msg.id} message={msg} />
               ))
             )}
+            {retrying && (
+              <div className="text-center text-xs text-muted-foreground">
+                请求失败，重试中 ({retrying.retry}/{retrying.max_retries})
+              </div>
+            )}
             <div ref={scrollRef} className="h-1" />
           </div>
         </ScrollArea>
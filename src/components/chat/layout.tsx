@@ -1,5 +1,6 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import {
   Sheet,
   SheetContent,
@@ -25,6 +26,20 @@ export default function ChatLayout() {
     loadSessions();
   }, []);
 
+  // 会话被后端更新 (比如自动生成了标题) 时，替换列表里对应的那一项
+  useEffect(() => {
+    const unlistenPromise = listen<Conversation>("session-updated", (event) => {
+      const updated = event.payload;
+      setSessions((prev) =>
+        prev.map((session) => (session.id === updated.id ? updated : session)),
+      );
+    });
+
+    return () => {
+      unlistenPromise.then((f) => f());
+    };
+  }, []);
+
   async function loadSessions() {
     try {
       const data = await invoke<Conversation[]>("get_sessions");
@@ -41,10 +56,8 @@ export default function ChatLayout() {
   // 2. 新建会话逻辑
   async function handleNewChat() {
     try {
-      // 简单起见，标题先用时间或者固定文本，未来可以让 AI 自动总结标题
-      const newSession = await invoke<Conversation>("create_new_chat", {
-        title: `新对话 ${new Date().toLocaleTimeString()}`,
-      });
+      // 不传标题：后端先用占位标题，第一轮问答之后自动起标题
+      const newSession = await invoke<Conversation>("create_new_chat", {});
 
       // 更新列表并自动选中新建的
       setSessions([newSession, ...sessions]);
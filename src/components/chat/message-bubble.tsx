@@ -133,6 +133,12 @@ export function MessageBubble({ message }: MessageBubbleProps) {
             </ReactMarkdown>
           </div>
         )}
+        {/* 生成失败的回复：显示错误详情 */}
+        {message.status === "error" && message.error && (
+          <div className="mt-1 text-xs text-destructive wrap-break-word">
+            {message.error}
+          </div>
+        )}
       </div>
     </div>
   );
@@ -4,6 +4,11 @@ export interface Message {
   role: "user" | "assistant";
   content: string;
   created_at?: string;
+  status?: "complete" | "cancelled" | "error";
+  error?: string | null; // 生成失败时的错误详情
+  model_name?: string | null; // 实际生成这条回复的模型
+  parent_id?: number | null; // 父消息，同一个 parent_id 的消息互为不同版本 (分支)
+  sibling_ids?: number[]; // 包括自己在内的所有兄弟消息，多于一个时说明这里有分支
 }
 
 //会话类型
@@ -11,4 +16,19 @@ export interface Conversation {
   id: number;
   title: string;
   created_at: string;
+  model_id?: number | null; // 为空时跟随默认模型
+  parameters?: string | null; // 会话上的生成参数覆盖 (JSON)
+  persona_id?: number | null; // 为空时不使用人设
+  title_is_auto?: boolean; // 还是占位标题，问答之后会自动起标题
+}
+
+// 人设：系统提示词 + 默认模型 + 默认参数
+export interface Persona {
+  id: number;
+  name: string;
+  system_prompt: string;
+  model_id?: number | null;
+  parameters?: string | null;
+  avatar?: string | null;
+  created_at?: string;
 }